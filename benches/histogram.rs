@@ -0,0 +1,27 @@
+//! Time recording into histograms with many buckets, run with `cargo bench`
+
+use std::{hint::black_box, time::Instant};
+
+use metrics::{histogram, set_default_local_recorder};
+use otlp_metrics_exporter::otlp_recorder::OtlpRecorder;
+
+const RECORDS: u32 = 1_000_000;
+
+fn main() {
+    for buckets in [10, 100, 1000] {
+        let recorder = OtlpRecorder::new("bench", "1", "histogram")
+            .histogram_buckets((1..=buckets).map(f64::from).collect());
+        let _guard = set_default_local_recorder(&recorder);
+        let histogram = histogram!("bench_histogram");
+
+        let started = Instant::now();
+        for i in 0..RECORDS {
+            histogram.record(black_box(f64::from(i % (buckets + 10))));
+        }
+        let elapsed = started.elapsed();
+        println!(
+            "{buckets} buckets: {:.1} ns per record",
+            elapsed.as_nanos() as f64 / f64::from(RECORDS)
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Source of timestamps for recorded metrics
+pub trait Clock: Send + Sync {
+    /// Nanoseconds since the unix epoch
+    fn now_unix_nanos(&self) -> u64;
+}
+
+/// Granularity of exported timestamps, values are still given in nanoseconds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimePrecision {
+    #[default]
+    Nanoseconds,
+    Microseconds,
+    Milliseconds,
+}
+
+impl TimePrecision {
+    /// Truncate nanoseconds to a multiple of the precision
+    pub fn truncate(self, nanos: u64) -> u64 {
+        let unit = match self {
+            Self::Nanoseconds => return nanos,
+            Self::Microseconds => 1_000,
+            Self::Milliseconds => 1_000_000,
+        };
+        nanos - nanos % unit
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    /// Reports 0 for times before the unix epoch, the recorder never reports steps backwards
+    fn now_unix_nanos(&self) -> u64 {
+        nanos(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// Nanoseconds of a duration, saturating instead of wrapping after the year 2554
+fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// Clock that only moves when told to, useful for tests and simulations
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    /// Start at the given duration since the unix epoch
+    pub fn new(now: Duration) -> Self {
+        Self {
+            now: AtomicU64::new(nanos(now)),
+        }
+    }
+
+    pub fn set(&self, now: Duration) {
+        self.now.store(nanos(now), Ordering::Release);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.fetch_add(nanos(by), Ordering::AcqRel);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_unix_nanos(&self) -> u64 {
+        self.now.load(Ordering::Acquire)
+    }
+}
+
+/// Clock shared between the recorder and its metric values
+///
+/// Never goes backwards, when the clock steps back (NTP adjustments, VM resume) the latest
+/// timestamp is reported until the clock catches up.
+#[derive(Clone)]
+pub(crate) struct SharedClock {
+    clock: Arc<dyn Clock>,
+    precision: TimePrecision,
+    latest: Arc<AtomicU64>,
+}
+
+impl SharedClock {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            precision: TimePrecision::default(),
+            latest: Default::default(),
+        }
+    }
+
+    pub fn with_precision(mut self, precision: TimePrecision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    pub fn precision(&self) -> TimePrecision {
+        self.precision
+    }
+
+    pub fn now(&self) -> u64 {
+        let now = self.precision.truncate(self.clock.now_unix_nanos());
+        let latest = self.latest.fetch_max(now, Ordering::AcqRel);
+        now.max(latest)
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new(Duration::from_secs(10));
+        assert_eq!(clock.now_unix_nanos(), 10_000_000_000);
+        assert_eq!(clock.now_unix_nanos(), 10_000_000_000);
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(clock.now_unix_nanos(), 10_005_000_000);
+        clock.set(Duration::from_secs(1));
+        assert_eq!(clock.now_unix_nanos(), 1_000_000_000);
+    }
+
+    #[test]
+    fn test_time_precision() {
+        let clock = ManualClock::new(Duration::from_millis(1_739_394_449_205));
+        assert_eq!(clock.now_unix_nanos(), 1_739_394_449_205_000_000);
+        clock.advance(Duration::from_nanos(123_456));
+        let clock: Arc<dyn Clock> = Arc::new(clock);
+
+        let now = |precision| {
+            SharedClock::new(clock.clone())
+                .with_precision(precision)
+                .now()
+        };
+        assert_eq!(now(TimePrecision::Nanoseconds), 1_739_394_449_205_123_456);
+        assert_eq!(now(TimePrecision::Microseconds), 1_739_394_449_205_123_000);
+        assert_eq!(now(TimePrecision::Milliseconds), 1_739_394_449_205_000_000);
+
+        let clock = ManualClock::new(Duration::MAX);
+        assert_eq!(clock.now_unix_nanos(), u64::MAX);
+    }
+
+    #[test]
+    fn test_shared_clock_never_goes_backwards() {
+        let manual = Arc::new(ManualClock::new(Duration::from_secs(10)));
+        let clock = SharedClock::new(manual.clone());
+        assert_eq!(clock.now(), 10_000_000_000);
+
+        manual.set(Duration::from_secs(5));
+        assert_eq!(clock.now(), 10_000_000_000);
+        assert_eq!(clock.clone().now(), 10_000_000_000);
+
+        manual.set(Duration::from_secs(11));
+        assert_eq!(clock.now(), 11_000_000_000);
+    }
+}
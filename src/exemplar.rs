@@ -0,0 +1,122 @@
+use std::{
+    cell::Cell,
+    sync::{Arc, Mutex},
+};
+
+use metrics::{Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn};
+
+use crate::clock::SharedClock;
+
+pub(crate) type TraceIdHook = Arc<dyn Fn() -> Option<String> + Send + Sync>;
+
+thread_local! {
+    static TRACE_CONTEXT: Cell<Option<TraceContext>> = const { Cell::new(None) };
+}
+
+/// Trace and span attached to exemplars recorded on the current thread
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+}
+
+/// Attach the trace to observations recorded on this thread until cleared
+///
+/// Only recorders with exemplars enabled capture the trace.
+pub fn set_trace_context(trace_id: [u8; 16], span_id: [u8; 8]) {
+    TRACE_CONTEXT.set(Some(TraceContext { trace_id, span_id }));
+}
+
+pub fn clear_trace_context() {
+    TRACE_CONTEXT.set(None);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Exemplar {
+    pub value: f64,
+    pub time: u64,
+    pub trace: TraceContext,
+}
+
+/// Store the observation in the slot when recorded within a trace
+pub(crate) fn capture(slot: &Mutex<Option<Exemplar>>, value: f64, clock: &SharedClock) {
+    if let Some(trace) = TRACE_CONTEXT.get() {
+        *slot.lock().expect("exemplar lock") = Some(Exemplar {
+            value,
+            time: clock.now(),
+            trace,
+        });
+    }
+}
+
+pub(crate) fn collect(slots: &[Mutex<Option<Exemplar>>]) -> Vec<Exemplar> {
+    slots
+        .iter()
+        .filter_map(|s| *s.lock().expect("exemplar lock"))
+        .collect()
+}
+
+pub(crate) fn reset(slots: &[Mutex<Option<Exemplar>>]) {
+    for slot in slots {
+        *slot.lock().expect("exemplar lock") = None;
+    }
+}
+
+/// Handle storing the trace id returned by the hook before every update
+pub(crate) struct Traced<T> {
+    handle: T,
+    hook: TraceIdHook,
+    slot: Arc<Mutex<Option<String>>>,
+}
+
+impl<T> Traced<T> {
+    pub(crate) fn new(handle: T, hook: TraceIdHook, slot: Arc<Mutex<Option<String>>>) -> Self {
+        Self { handle, hook, slot }
+    }
+
+    fn capture(&self) {
+        if let Some(trace_id) = (self.hook)() {
+            *self.slot.lock().expect("trace id lock") = Some(trace_id);
+        }
+    }
+}
+
+impl CounterFn for Traced<Counter> {
+    fn increment(&self, value: u64) {
+        self.capture();
+        self.handle.increment(value);
+    }
+
+    fn absolute(&self, value: u64) {
+        self.capture();
+        self.handle.absolute(value);
+    }
+}
+
+impl GaugeFn for Traced<Gauge> {
+    fn increment(&self, value: f64) {
+        self.capture();
+        self.handle.increment(value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.capture();
+        self.handle.decrement(value);
+    }
+
+    fn set(&self, value: f64) {
+        self.capture();
+        self.handle.set(value);
+    }
+}
+
+impl HistogramFn for Traced<Histogram> {
+    fn record(&self, value: f64) {
+        self.capture();
+        self.handle.record(value);
+    }
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -1,98 +1,328 @@
+use std::io::{self, Write};
+
 use json::{object, JsonValue};
 use metrics::Key;
+use tracing::warn;
 
-use crate::metric::{CounterValue, GaugeValue, HistogramValue, MetricData, MetricType};
+use crate::{
+    exemplar::{hex, Exemplar},
+    metric::{
+        AttributeValue, CounterValue, DoubleCounterValue, ExponentialBuckets,
+        ExponentialHistogramValue, GaugeValue, HistogramValue, MetricData, MetricType,
+        SummaryValue,
+    },
+    otlp_recorder::{NonFiniteValues, OtlpRecorder},
+};
 
-pub fn metrics_to_json(
-    name: &str,
-    version: &str,
-    instance_id: &str,
-    values: &[&(Key, MetricData)],
-) -> String {
-    let value = root(name, version, instance_id, values);
-    json::stringify(value)
+pub fn write_metrics<W: Write>(
+    recorder: &OtlpRecorder,
+    values: &[(&Key, &MetricData)],
+    writer: &mut W,
+) -> io::Result<()> {
+    root(recorder, values).write(writer)
 }
 
-fn root(name: &str, version: &str, instance_id: &str, values: &[&(Key, MetricData)]) -> JsonValue {
-    object! {
+pub fn root(recorder: &OtlpRecorder, values: &[(&Key, &MetricData)]) -> JsonValue {
+    let mut root = object! {
         "resourceMetrics": [{
             "resource": {
-                "attributes": [
-                    attr("service.name", name),
-                    attr("service.version", version),
-                    attr("service.instance.id", instance_id),
-                ]
+                "attributes": resource_attributes(recorder),
             },
             "scopeMetrics": [{
-                "metrics": values.iter().map(|(k, v)| {
+                "scope": {
+                    "name": recorder.scope_name.as_str(),
+                    "version": recorder.scope_version.as_str(),
+                },
+                "metrics": values.iter().filter_map(|(k, v)| {
                     match &v.metric_type {
-                        MetricType::Counter(m) => counter(k, v, m),
-                        MetricType::Gauge(m) => gauge(k, v, m),
-                        MetricType::Histogram(m) => histogram(k, v, m),
+                        MetricType::Counter(m) => Some(counter(recorder, k, v, m)),
+                        MetricType::Gauge(m) => gauge(recorder, k, v, m),
+                        MetricType::Histogram(m) => histogram(recorder, k, v, m),
+                        MetricType::DoubleCounter(m) => double_counter(recorder, k, v, m),
+                        MetricType::Summary(m) => summary(recorder, k, v, m),
+                        MetricType::ExponentialHistogram(m) => {
+                            exponential_histogram(recorder, k, v, m)
+                        }
                     }
                 }).collect::<Vec<_>>(),
             }]
         }]
+    };
+    let resource_metrics = &mut root["resourceMetrics"][0];
+    if let Some(url) = &recorder.resource_schema_url {
+        resource_metrics["schemaUrl"] = url.as_str().into();
+    }
+    if let Some(url) = &recorder.scope_schema_url {
+        resource_metrics["scopeMetrics"][0]["schemaUrl"] = url.as_str().into();
     }
+    root
 }
 
-fn counter(key: &Key, data: &MetricData, value: &CounterValue) -> JsonValue {
+fn resource_attributes(recorder: &OtlpRecorder) -> Vec<JsonValue> {
+    [
+        attr("service.name", &recorder.name),
+        attr("service.version", &recorder.version),
+        attr("service.instance.id", &recorder.instance_id),
+    ]
+    .into_iter()
+    .chain(recorder.resource_attributes.iter().map(|(k, v)| attr(k, v)))
+    .collect()
+}
+
+fn counter(
+    recorder: &OtlpRecorder,
+    key: &Key,
+    data: &MetricData,
+    value: &CounterValue,
+) -> JsonValue {
+    let mut data_point = object! {
+        "asInt": value.value().to_string(),
+        "startTimeUnixNano": data.start_time(),
+        "timeUnixNano": data.point_time(value.time()),
+        "attributes": attributes(recorder, key, data),
+        "droppedAttributesCount": recorder.dropped_attributes(key, data),
+        "flags": 0,
+    };
+    if !value.exemplars.is_empty() {
+        data_point["exemplars"] = exemplars(recorder, key, value.exemplars()).into();
+    }
     object! {
-        "name": key.name(),
-        "unit": data.unit(),
+        "name": recorder.metric_name(key).as_ref(),
+        "unit": recorder.unit(key, data),
+        "description": data.description.to_string(),
+        "sum": {
+            "aggregationTemporality": recorder.temporality as u8,
+            "isMonotonic": recorder.is_monotonic(key),
+            "dataPoints": [data_point]
+        }
+    }
+}
+
+fn double_counter(
+    recorder: &OtlpRecorder,
+    key: &Key,
+    data: &MetricData,
+    value: &DoubleCounterValue,
+) -> Option<JsonValue> {
+    let double_value = finite(recorder, key, value.value())?;
+    Some(object! {
+        "name": recorder.metric_name(key).as_ref(),
+        "unit": recorder.unit(key, data),
         "description": data.description.to_string(),
         "sum": {
-            "aggregationTemporality": 2,
-            "isMonotonic": true,
+            "aggregationTemporality": recorder.temporality as u8,
+            "isMonotonic": recorder.is_monotonic(key),
             "dataPoints": [
                 {
-                    "asInt": value.value(),
-                    "startTimeUnixNano": data.start_time,
-                    "timeUnixNano": value.time(),
-                    "attributes": key.labels().map(|l| attr(l.key(), l.value())).collect::<Vec<_>>()
+                    "asDouble": double_value,
+                    "startTimeUnixNano": data.start_time(),
+                    "timeUnixNano": data.point_time(value.time()),
+                    "attributes": attributes(recorder, key, data),
+                    "droppedAttributesCount": recorder.dropped_attributes(key, data),
+                    "flags": 0,
                 }
             ]
         }
-    }
+    })
 }
 
-fn gauge(key: &Key, data: &MetricData, value: &GaugeValue) -> JsonValue {
-    object! {
-        "name": key.name(),
-        "unit": data.unit(),
+fn gauge(
+    recorder: &OtlpRecorder,
+    key: &Key,
+    data: &MetricData,
+    value: &GaugeValue,
+) -> Option<JsonValue> {
+    let (gauge_value, time) = value.snapshot();
+    let gauge_value = finite(recorder, key, gauge_value)?;
+    Some(object! {
+        "name": recorder.metric_name(key).as_ref(),
+        "unit": recorder.unit(key, data),
         "description": data.description.to_string(),
         "gauge": {
             "dataPoints": [
                 {
-                    "asDouble": value.value(),
-                    "startTimeUnixNano": data.start_time,
-                    "timeUnixNano": value.time(),
-                    "attributes": key.labels().map(|l| attr(l.key(), l.value())).collect::<Vec<_>>()
+                    "asDouble": gauge_value,
+                    "startTimeUnixNano": data.start_time(),
+                    "timeUnixNano": data.point_time(time),
+                    "attributes": attributes(recorder, key, data),
+                    "droppedAttributesCount": recorder.dropped_attributes(key, data),
+                    "flags": 0,
                 }
             ]
         }
+    })
+}
+
+fn histogram(
+    recorder: &OtlpRecorder,
+    key: &Key,
+    data: &MetricData,
+    value: &HistogramValue,
+) -> Option<JsonValue> {
+    let (count, bucket_counts) = value.counts();
+    let mut data_point = object! {
+        "startTimeUnixNano": data.start_time(),
+        "timeUnixNano": data.point_time(value.time()),
+        "count": count.to_string(),
+        "sum": finite(recorder, key, value.sum())?,
+        "attributes": attributes(recorder, key, data),
+        "droppedAttributesCount": recorder.dropped_attributes(key, data),
+        "flags": 0,
+        "bucketCounts": bucket_counts,
+        "explicitBounds": value.explicit_bounds(),
+    };
+    if let (Some(min), Some(max)) = (value.min(), value.max()) {
+        data_point["min"] = finite(recorder, key, min)?.into();
+        data_point["max"] = finite(recorder, key, max)?.into();
+    }
+    if !value.exemplars.is_empty() {
+        data_point["exemplars"] = exemplars(recorder, key, value.exemplars()).into();
+    }
+    Some(object! {
+        "name": recorder.metric_name(key).as_ref(),
+        "unit": recorder.unit(key, data),
+        "description": data.description.to_string(),
+        "histogram": {
+            "aggregationTemporality": recorder.temporality as u8,
+            "dataPoints": [data_point]
+        }
+    })
+}
+
+fn exponential_histogram(
+    recorder: &OtlpRecorder,
+    key: &Key,
+    data: &MetricData,
+    value: &ExponentialHistogramValue,
+) -> Option<JsonValue> {
+    let snapshot = value.snapshot();
+    let mut data_point = object! {
+        "startTimeUnixNano": data.start_time(),
+        "timeUnixNano": data.point_time(value.time()),
+        "count": snapshot.count.to_string(),
+        "sum": finite(recorder, key, snapshot.sum)?,
+        "scale": snapshot.scale,
+        "zeroCount": snapshot.zero_count.to_string(),
+        "positive": exponential_buckets(&snapshot.positive),
+        "negative": exponential_buckets(&snapshot.negative),
+        "attributes": attributes(recorder, key, data),
+        "droppedAttributesCount": recorder.dropped_attributes(key, data),
+        "flags": 0,
+    };
+    if let (Some(min), Some(max)) = (snapshot.min, snapshot.max) {
+        data_point["min"] = min.into();
+        data_point["max"] = max.into();
     }
+    Some(object! {
+        "name": recorder.metric_name(key).as_ref(),
+        "unit": recorder.unit(key, data),
+        "description": data.description.to_string(),
+        "exponentialHistogram": {
+            "aggregationTemporality": recorder.temporality as u8,
+            "dataPoints": [data_point]
+        }
+    })
 }
 
-fn histogram(key: &Key, data: &MetricData, value: &HistogramValue) -> JsonValue {
+fn exponential_buckets(buckets: &ExponentialBuckets) -> JsonValue {
     object! {
-        "name": key.name(),
-        "unit": data.unit(),
+        "offset": buckets.offset,
+        "bucketCounts": buckets.counts.as_slice(),
+    }
+}
+
+fn summary(
+    recorder: &OtlpRecorder,
+    key: &Key,
+    data: &MetricData,
+    value: &SummaryValue,
+) -> Option<JsonValue> {
+    let quantile_values = value
+        .quantile_values()
+        .into_iter()
+        .map(|(quantile, value)| {
+            Some(object! {
+                "quantile": quantile,
+                "value": finite(recorder, key, value)?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let data_point = object! {
+        "startTimeUnixNano": data.start_time(),
+        "timeUnixNano": data.point_time(value.time()),
+        "count": value.count().to_string(),
+        "sum": finite(recorder, key, value.sum())?,
+        "quantileValues": quantile_values,
+        "attributes": attributes(recorder, key, data),
+        "droppedAttributesCount": recorder.dropped_attributes(key, data),
+        "flags": 0,
+    };
+    Some(object! {
+        "name": recorder.metric_name(key).as_ref(),
+        "unit": recorder.unit(key, data),
         "description": data.description.to_string(),
-        "histogram": {
-            "aggregationTemporality": 2,
-            "dataPoints": [
-                {
-                    "startTimeUnixNano": data.start_time,
-                    "timeUnixNano": value.time(),
-                    "count": value.count(),
-                    "sum": value.sum(),
-                    "attributes": key.labels().map(|l| attr(l.key(), l.value())).collect::<Vec<_>>(),
-                    "bucketCounts": value.bucket_count(),
-                    "explicitBounds": value.explicit_bounds(),
-                }
-            ]
+        "summary": {
+            "dataPoints": [data_point]
         }
+    })
+}
+
+fn exemplars(recorder: &OtlpRecorder, key: &Key, exemplars: Vec<Exemplar>) -> Vec<JsonValue> {
+    exemplars
+        .into_iter()
+        .filter_map(|e| {
+            Some(object! {
+                "timeUnixNano": e.time,
+                "asDouble": finite(recorder, key, e.value)?,
+                "traceId": hex(&e.trace.trace_id),
+                "spanId": hex(&e.trace.span_id),
+            })
+        })
+        .collect()
+}
+
+/// Apply the non finite value policy, json has no representation for NaN and infinity
+fn finite(recorder: &OtlpRecorder, key: &Key, value: f64) -> Option<f64> {
+    if value.is_finite() {
+        return Some(value);
+    }
+    match recorder.non_finite_values {
+        NonFiniteValues::Zero => {
+            warn!("Metric {key} has non finite value {value}, exporting 0");
+            Some(0.0)
+        }
+        NonFiniteValues::Clamp => Some(if value.is_nan() {
+            0.0
+        } else {
+            value.clamp(f64::MIN, f64::MAX)
+        }),
+        NonFiniteValues::Skip => None,
+    }
+}
+
+fn attributes(recorder: &OtlpRecorder, key: &Key, data: &MetricData) -> Vec<JsonValue> {
+    recorder
+        .attributes(key, data)
+        .map(|(key, value)| {
+            if recorder.typed_attributes {
+                typed_attr(&key, AttributeValue::from(value.as_ref()))
+            } else {
+                attr(&key, &value)
+            }
+        })
+        .collect()
+}
+
+fn typed_attr(key: &str, value: AttributeValue) -> JsonValue {
+    let value = match value {
+        AttributeValue::String(v) => object! { "stringValue": v },
+        AttributeValue::Int(v) => object! { "intValue": v },
+        AttributeValue::Double(v) => object! { "doubleValue": v },
+        AttributeValue::Bool(v) => object! { "boolValue": v },
+    };
+    object! {
+        "key": key,
+        "value": value
     }
 }
 
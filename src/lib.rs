@@ -1,12 +1,16 @@
 use std::sync::Arc;
 
-use metrics::set_global_recorder;
+use metrics::SetRecorderError;
 use otlp_recorder::OtlpRecorder;
 
+pub mod clock;
+pub mod exemplar;
 mod json;
 mod metric;
 pub mod otlp_recorder;
-mod time;
+mod prometheus;
+mod protobuf;
+pub mod snapshot;
 pub mod transport;
 
 /// Install recorder globally
@@ -17,20 +21,105 @@ pub mod transport;
 /// use otlp_metrics_exporter::install_recorder;
 /// use metrics::{counter, gauge, histogram};
 ///
-/// let recorder = install_recorder(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), "instance1");
+/// let recorder = install_recorder(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), "instance1").unwrap();
 /// counter!("test_counter", "label1" => "label_value1").increment(1);
 /// gauge!("test_gauge", "label2" => "label_value2").set(10);
 /// histogram!("test_histogram", "label3" => "label_value3").record(10);
-/// recorder.to_json(None);
+/// recorder.to_json();
+///
+/// // the metrics crate does not allow replacing the global recorder
+/// assert!(install_recorder("other", "1", "instance1").is_err());
 /// ```
 pub fn install_recorder(
     name: impl ToString,
     version: impl ToString,
     instance_id: impl ToString,
-) -> Arc<OtlpRecorder> {
-    let recorder = Arc::new(OtlpRecorder::new(name, version, instance_id));
-    set_global_recorder(recorder.clone()).expect("Recorder installed");
-    recorder
+) -> Result<Arc<OtlpRecorder>, SetRecorderError<Arc<OtlpRecorder>>> {
+    OtlpRecorder::new(name, version, instance_id).install()
+}
+
+/// Install recorder globally using the hostname as instance id
+///
+/// The hostname is read from the `HOSTNAME` environment variable or `/etc/hostname`,
+/// `unknown` is used when neither is available.
+///
+/// # Example
+///
+/// ```rust
+/// use otlp_metrics_exporter::install_recorder_with_hostname;
+/// use metrics::counter;
+///
+/// let recorder = install_recorder_with_hostname(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")).unwrap();
+/// counter!("test_counter", "label1" => "label_value1").increment(1);
+/// recorder.to_json();
+/// ```
+pub fn install_recorder_with_hostname(
+    name: impl ToString,
+    version: impl ToString,
+) -> Result<Arc<OtlpRecorder>, SetRecorderError<Arc<OtlpRecorder>>> {
+    install_recorder(name, version, hostname())
+}
+
+/// Install recorder globally configured by the OpenTelemetry resource environment variables
+///
+/// The service name is read from `OTEL_SERVICE_NAME`, `OTEL_RESOURCE_ATTRIBUTES` given as
+/// `key=value` pairs are exported as resource attributes. `service.version` and
+/// `service.instance.id` attributes set the version and instance id, which default to an empty
+/// version and the hostname. See [`transport::TransportConfig::from_env`] for the receiver.
+///
+/// # Example
+///
+/// ```rust
+/// use otlp_metrics_exporter::install_recorder_from_env;
+/// use otlp_metrics_exporter::transport::TransportConfig;
+///
+/// let recorder = install_recorder_from_env().unwrap();
+/// let config = TransportConfig::from_env();
+/// ```
+pub fn install_recorder_from_env() -> Result<Arc<OtlpRecorder>, SetRecorderError<Arc<OtlpRecorder>>>
+{
+    recorder_from_env(|name| std::env::var(name).ok()).install()
+}
+
+fn recorder_from_env(var: impl Fn(&str) -> Option<String>) -> OtlpRecorder {
+    let mut attributes = var("OTEL_RESOURCE_ATTRIBUTES")
+        .map(|attributes| transport::key_values(&attributes))
+        .unwrap_or_default();
+    let mut take = |name: &str| {
+        let index = attributes.iter().position(|(k, _)| k == name)?;
+        Some(attributes.remove(index).1)
+    };
+    let service_name = take("service.name");
+    let version = take("service.version").unwrap_or_default();
+    let instance_id = take("service.instance.id").unwrap_or_else(hostname);
+    let name = var("OTEL_SERVICE_NAME")
+        .filter(|name| !name.trim().is_empty())
+        .or(service_name)
+        .unwrap_or_else(|| "unknown_service".to_string());
+    OtlpRecorder::new(name, version, instance_id).resource_attributes(attributes)
+}
+
+fn hostname() -> String {
+    detect_hostname().unwrap_or_else(|| "unknown".to_string())
+}
+
+fn detect_hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Install recorder globally or return the recorder installed previously
+///
+/// Useful in tests and libraries which can not ensure a single installation.
+pub fn install_recorder_or_existing(
+    name: impl ToString,
+    version: impl ToString,
+    instance_id: impl ToString,
+) -> Result<Arc<OtlpRecorder>, SetRecorderError<Arc<OtlpRecorder>>> {
+    OtlpRecorder::new(name, version, instance_id).install_or_existing()
 }
 
 #[cfg(test)]
@@ -42,14 +131,27 @@ mod tests {
         set_default_local_recorder, Unit,
     };
 
-    use crate::time::set_time;
+    use crate::{
+        clock::{ManualClock, TimePrecision},
+        exemplar::{clear_trace_context, set_trace_context},
+        otlp_recorder::{
+            updated_within, AggregationTemporality, DuplicateLabels, NonFiniteValues,
+            ResourceDetectors,
+        },
+        snapshot::{MetricSnapshot, SnapshotValue},
+    };
 
     use super::*;
 
+    fn test_clock() -> Arc<ManualClock> {
+        Arc::new(ManualClock::new(Duration::from_millis(1739394449205)))
+    }
+
     #[test]
     fn test_recorder_to_json() {
-        set_time(1739394449205);
-        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_recorder_to_json");
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_recorder_to_json")
+            .scope("test", "1")
+            .clock(test_clock());
         let _guard = set_default_local_recorder(&recorder);
         for i in 1..3 {
             counter!("test_counter", "label1" => "label_value1").increment(1);
@@ -59,19 +161,20 @@ mod tests {
         }
 
         assert_eq!(
-            recorder.to_json(None),
-            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_recorder_to_json"}}]},"scopeMetrics":[{"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":2,"startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394450105000000,"attributes":[{"key":"label1","value":{"stringValue":"label_value1"}}]}]}},{"name":"test_gauge","unit":"1","description":"","gauge":{"dataPoints":[{"asDouble":20,"startTimeUnixNano":1739394449505000000,"timeUnixNano":1739394450205000000,"attributes":[{"key":"label2","value":{"stringValue":"label_value2"}}]}]}},{"name":"test_histogram","unit":"1","description":"","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449705000000,"timeUnixNano":1739394450305000000,"count":2,"sum":30,"attributes":[{"key":"label3","value":{"stringValue":"label_value3"}}],"bucketCounts":[],"explicitBounds":[]}]}},{"name":"test_histogram_with_buckets","unit":"1","description":"","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449905000000,"timeUnixNano":1739394450405000000,"count":2,"sum":30,"attributes":[{"key":"buckets","value":{"stringValue":"10,30"}}],"bucketCounts":[1,1,0],"explicitBounds":[10,30]}]}}]}]}]}"#,
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_recorder_to_json"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"2","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[{"key":"label1","value":{"stringValue":"label_value1"}}],"droppedAttributesCount":0,"flags":0}]}},{"name":"test_gauge","unit":"1","description":"","gauge":{"dataPoints":[{"asDouble":20,"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[{"key":"label2","value":{"stringValue":"label_value2"}}],"droppedAttributesCount":0,"flags":0}]}},{"name":"test_histogram","unit":"1","description":"","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"2","sum":30,"attributes":[{"key":"label3","value":{"stringValue":"label_value3"}}],"droppedAttributesCount":0,"flags":0,"bucketCounts":[],"explicitBounds":[],"min":10,"max":20}]}},{"name":"test_histogram_with_buckets","unit":"1","description":"","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"2","sum":30,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[1,1,0],"explicitBounds":[10,30],"min":10,"max":20}]}}]}]}]}"#,
         );
     }
 
     #[test]
     fn test_recorder_with_descriptions_and_units() {
-        set_time(1739394449205);
         let recorder = OtlpRecorder::new(
             "otlp-metrics",
             "1",
             "test_recorder_with_descriptions_and_units",
-        );
+        )
+        .scope("test", "1")
+        .clock(test_clock());
         let _guard = set_default_local_recorder(&recorder);
 
         describe_counter!("bytes_total", Unit::Bytes, "Counter for bytes");
@@ -87,60 +190,1689 @@ mod tests {
         histogram!("request_time").record(10);
 
         assert_eq!(
-            recorder.to_json(None),
-            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_recorder_with_descriptions_and_units"}}]},"scopeMetrics":[{"metrics":[{"name":"bytes_total","unit":"B","description":"Counter for bytes","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":1,"startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449405000000,"attributes":[]}]}},{"name":"limit_reached","unit":"%","description":"Gauge percent","gauge":{"dataPoints":[{"asDouble":10,"startTimeUnixNano":1739394449505000000,"timeUnixNano":1739394449605000000,"attributes":[]}]}},{"name":"request_time","unit":"ms","description":"Request time in milliseconds","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449705000000,"timeUnixNano":1739394449805000000,"count":1,"sum":10,"attributes":[],"bucketCounts":[],"explicitBounds":[]}]}}]}]}]}"#,
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_recorder_with_descriptions_and_units"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"bytes_total","unit":"B","description":"Counter for bytes","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}},{"name":"limit_reached","unit":"%","description":"Gauge percent","gauge":{"dataPoints":[{"asDouble":10,"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}},{"name":"request_time","unit":"ms","description":"Request time in milliseconds","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"1","sum":10,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[],"explicitBounds":[],"min":10,"max":10}]}}]}]}]}"#,
         );
     }
 
     #[test]
     fn test_metric_times() {
-        set_time(1739394449205);
-        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_metric_times");
+        let clock = test_clock();
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_metric_times")
+            .scope("test", "1")
+            .clock(clock.clone());
         let _guard = set_default_local_recorder(&recorder);
 
+        clock.advance(Duration::from_millis(100));
         counter!("test_counter", "label1" => "label_value1").increment(1);
 
         assert_eq!(
-            recorder.to_json(None),
-            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_metric_times"}}]},"scopeMetrics":[{"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":1,"startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449405000000,"attributes":[{"key":"label1","value":{"stringValue":"label_value1"}}]}]}}]}]}]}"#
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_metric_times"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449305000000,"attributes":[{"key":"label1","value":{"stringValue":"label_value1"}}],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
         );
 
+        clock.advance(Duration::from_millis(100));
         counter!("test_counter", "label1" => "label_value1").increment(1);
 
         assert_eq!(
-            recorder.to_json(None),
-            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_metric_times"}}]},"scopeMetrics":[{"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":2,"startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449505000000,"attributes":[{"key":"label1","value":{"stringValue":"label_value1"}}]}]}}]}]}]}"#
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_metric_times"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"2","startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449405000000,"attributes":[{"key":"label1","value":{"stringValue":"label_value1"}}],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
         );
     }
 
     #[test]
     fn test_output_only_changed_values() {
-        set_time(1739394449205);
-        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_output_only_changed_values");
+        let clock = test_clock();
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_output_only_changed_values")
+            .scope("test", "1")
+            .clock(clock.clone());
+        let _guard = set_default_local_recorder(&recorder);
+
+        clock.advance(Duration::from_millis(100));
+        counter!("test_counter", "label1" => "label_value1").increment(1);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_output_only_changed_values"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449305000000,"attributes":[{"key":"label1","value":{"stringValue":"label_value1"}}],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+
+        clock.advance(Duration::from_millis(100));
+
+        assert_eq!(
+            recorder.to_json_since(Duration::from_millis(101)),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_output_only_changed_values"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449305000000,"attributes":[{"key":"label1","value":{"stringValue":"label_value1"}}],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+
+        assert_eq!(
+            recorder.to_json_since(Duration::from_millis(99)),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_output_only_changed_values"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[]}]}]}"#
+        );
+        assert!(recorder
+            .snapshot_since(Duration::from_millis(99))
+            .is_empty());
+        assert_eq!(recorder.snapshot_since(Duration::from_millis(101)).len(), 1);
+        assert_eq!(
+            recorder.to_protobuf_since(Duration::from_millis(101)),
+            recorder.to_protobuf()
+        );
+        assert!(
+            recorder.to_json_value_since(Duration::from_millis(99))["resourceMetrics"][0]
+                ["scopeMetrics"][0]["metrics"]
+                .is_empty()
+        );
+
+        clock.advance(Duration::from_millis(100));
+        counter!("test_counter", "label1" => "label_value1").increment(1);
+
+        assert_eq!(
+            recorder.to_json_since(Duration::from_secs(99)),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_output_only_changed_values"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"2","startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449505000000,"attributes":[{"key":"label1","value":{"stringValue":"label_value1"}}],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_period_filter_with_metric_newer_than_clock() {
+        let now = 1739394449205000000;
+        let period = Duration::from_nanos(1);
+
+        assert!(updated_within(now + 1, now, period));
+        assert!(updated_within(now - 1, now, period));
+        assert!(!updated_within(now - 2, now, period));
+    }
+
+    #[test]
+    fn test_metrics_with_different_labels_are_separate_series() {
+        let recorder = OtlpRecorder::new(
+            "otlp-metrics",
+            "1",
+            "test_metrics_with_different_labels_are_separate_series",
+        )
+        .scope("test", "1")
+        .clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("foo", "a" => "2").increment(2);
+        counter!("foo", "a" => "1").increment(1);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_metrics_with_different_labels_are_separate_series"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"foo","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[{"key":"a","value":{"stringValue":"1"}}],"droppedAttributesCount":0,"flags":0}]}},{"name":"foo","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"2","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[{"key":"a","value":{"stringValue":"2"}}],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_same_name_with_different_labels_and_types() {
+        let recorder = OtlpRecorder::new(
+            "otlp-metrics",
+            "1",
+            "test_same_name_with_different_labels_and_types",
+        )
+        .scope("test", "1")
+        .clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("req", "path" => "/a").increment(1);
+        counter!("req", "path" => "/b").increment(5);
+        counter!("req", "path" => "/a").increment(1);
+        gauge!("req", "path" => "/c").set(3);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_same_name_with_different_labels_and_types"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"req","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"2","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[{"key":"path","value":{"stringValue":"/a"}}],"droppedAttributesCount":0,"flags":0}]}},{"name":"req","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"5","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[{"key":"path","value":{"stringValue":"/b"}}],"droppedAttributesCount":0,"flags":0}]}},{"name":"req","unit":"1","description":"","gauge":{"dataPoints":[{"asDouble":3,"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[{"key":"path","value":{"stringValue":"/c"}}],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_register_existing_name_as_different_type() {
+        let recorder = OtlpRecorder::new(
+            "otlp-metrics",
+            "1",
+            "test_register_existing_name_as_different_type",
+        )
+        .scope("test", "1")
+        .clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("x").increment(1);
+        gauge!("x").set(10);
+        histogram!("x").record(10);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_register_existing_name_as_different_type"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"x","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_description_after_registration() {
+        let recorder =
+            OtlpRecorder::new("otlp-metrics", "1", "test_description_after_registration")
+                .scope("test", "1")
+                .clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("bytes_total").increment(1);
+        describe_counter!("bytes_total", Unit::Bytes, "Counter for bytes");
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_description_after_registration"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"bytes_total","unit":"B","description":"Counter for bytes","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_description_between_registrations() {
+        let recorder = OtlpRecorder::new(
+            "otlp-metrics",
+            "1",
+            "test_description_between_registrations",
+        )
+        .clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        gauge!("memory_usage").set(1);
+        describe_gauge!("memory_usage", Unit::Bytes, "Memory used");
+        gauge!("memory_usage").set(2);
+
+        let output = recorder.to_json();
+        assert!(
+            output.contains(r#""name":"memory_usage","unit":"B","description":"Memory used""#),
+            "{output}"
+        );
+        assert!(output.contains(r#""asDouble":2"#), "{output}");
+    }
+
+    #[test]
+    fn test_delta_temporality() {
+        let clock = test_clock();
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_delta_temporality")
+            .scope("test", "1")
+            .clock(clock.clone())
+            .aggregation_temporality(AggregationTemporality::Delta);
+        let _guard = set_default_local_recorder(&recorder);
+
+        clock.advance(Duration::from_millis(100));
+        counter!("test_counter").increment(1);
+        gauge!("test_gauge").set(10);
+        histogram!("test_histogram", "buckets" => "10").record(10);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_delta_temporality"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":1,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449305000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}},{"name":"test_gauge","unit":"1","description":"","gauge":{"dataPoints":[{"asDouble":10,"startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449305000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}},{"name":"test_histogram","unit":"1","description":"","histogram":{"aggregationTemporality":1,"dataPoints":[{"startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449305000000,"count":"1","sum":10,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[1,0],"explicitBounds":[10],"min":10,"max":10}]}}]}]}]}"#
+        );
+
+        clock.advance(Duration::from_millis(100));
+        counter!("test_counter").increment(2);
+        histogram!("test_histogram", "buckets" => "10").record(20);
+        clock.advance(Duration::from_millis(100));
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_delta_temporality"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":1,"isMonotonic":true,"dataPoints":[{"asInt":"2","startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449405000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}},{"name":"test_gauge","unit":"1","description":"","gauge":{"dataPoints":[{"asDouble":10,"startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449305000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}},{"name":"test_histogram","unit":"1","description":"","histogram":{"aggregationTemporality":1,"dataPoints":[{"startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449405000000,"count":"1","sum":20,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[0,1],"explicitBounds":[10],"min":20,"max":20}]}}]}]}]}"#
+        );
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_delta_temporality"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_gauge","unit":"1","description":"","gauge":{"dataPoints":[{"asDouble":10,"startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449305000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_typed_attributes() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_typed_attributes")
+            .scope("test", "1")
+            .clock(test_clock())
+            .typed_attributes(true);
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("requests", "code" => "200", "ratio" => "0.5", "cached" => "true", "path" => "/a")
+            .increment(1);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_typed_attributes"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"requests","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[{"key":"code","value":{"intValue":200}},{"key":"ratio","value":{"doubleValue":0.5}},{"key":"cached","value":{"boolValue":true}},{"key":"path","value":{"stringValue":"/a"}}],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_custom_resource_attributes() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_custom_resource_attributes")
+            .scope("test", "1")
+            .clock(test_clock())
+            .resource_attributes(vec![(
+                "deployment.environment".to_string(),
+                "prod".to_string(),
+            )]);
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter").increment(1);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_custom_resource_attributes"}},{"key":"deployment.environment","value":{"stringValue":"prod"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_custom_scope() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_custom_scope")
+            .clock(test_clock())
+            .scope("my-library", "2.0.1");
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter").increment(1);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_custom_scope"}}]},"scopeMetrics":[{"scope":{"name":"my-library","version":"2.0.1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+
+        let default = OtlpRecorder::new("otlp-metrics", "1", "test_custom_scope");
+        let json = default.to_json_value();
+        let scope = &json["resourceMetrics"][0]["scopeMetrics"][0]["scope"];
+        assert_eq!(scope["name"], env!("CARGO_PKG_NAME"));
+        assert_eq!(scope["version"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn test_to_writer_matches_to_json() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_to_writer_matches_to_json")
+            .clock(test_clock());
         let _guard = set_default_local_recorder(&recorder);
 
         counter!("test_counter", "label1" => "label_value1").increment(1);
+        gauge!("test_gauge").set(10);
+        histogram!("test_histogram", "buckets" => "10,30").record(10);
+
+        let mut output = Vec::new();
+        recorder.to_writer(&mut output).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), recorder.to_json());
+    }
+
+    #[test]
+    fn test_recorder_to_prometheus() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_recorder_to_prometheus")
+            .clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        describe_counter!("http.requests", "Handled \"http\" requests");
+        counter!("http.requests", "method" => "GET").increment(3);
+        counter!("http.requests", "method" => "POST").increment(1);
+        describe_gauge!("memory_usage", Unit::Bytes, "Memory used");
+        gauge!("memory_usage").set(1024.5);
+        histogram!("request_time", "buckets" => "10,30").record(5);
+        histogram!("request_time", "buckets" => "10,30").record(20);
+        histogram!("request_time", "buckets" => "10,30").record(40);
+
+        assert_eq!(
+            recorder.to_prometheus(),
+            r#"# HELP http_requests_total Handled "http" requests
+# TYPE http_requests_total counter
+http_requests_total{method="GET"} 3
+http_requests_total{method="POST"} 1
+# HELP memory_usage Memory used
+# TYPE memory_usage gauge
+memory_usage 1024.5
+# TYPE request_time histogram
+request_time_bucket{le="10"} 1
+request_time_bucket{le="30"} 2
+request_time_bucket{le="+Inf"} 3
+request_time_sum 65
+request_time_count 3
+"#
+        );
+    }
+
+    #[test]
+    fn test_prometheus_keeps_delta_values() {
+        let clock = test_clock();
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_prometheus_delta")
+            .clock(clock.clone())
+            .aggregation_temporality(AggregationTemporality::Delta);
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("requests").increment(1);
+        counter!("errors").increment(1);
+        recorder.to_json();
+
+        clock.advance(Duration::from_secs(1));
+        counter!("requests").increment(7);
+
+        let scrape = recorder.to_prometheus();
+        assert!(scrape.contains("requests_total 7\n"));
+        assert!(scrape.contains("errors_total"));
+        assert_eq!(recorder.to_prometheus(), scrape);
+        assert!(recorder.to_openmetrics().contains("requests_total 7\n"));
+        assert!(recorder.to_json().contains(r#""asInt":"7""#));
+    }
+
+    #[test]
+    fn test_default_histogram_buckets() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_default_histogram_buckets")
+            .scope("test", "1")
+            .clock(test_clock())
+            .histogram_buckets(vec![1.0, 5.0])
+            .metric_histogram_buckets("request_time", vec![10.0, 30.0]);
+        let _guard = set_default_local_recorder(&recorder);
+
+        histogram!("request_time").record(20);
+        histogram!("response_size").record(3);
 
         assert_eq!(
-            recorder.to_json(None),
-            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_output_only_changed_values"}}]},"scopeMetrics":[{"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":1,"startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449405000000,"attributes":[{"key":"label1","value":{"stringValue":"label_value1"}}]}]}}]}]}]}"#
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_default_histogram_buckets"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"request_time","unit":"1","description":"","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"1","sum":20,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[0,1,0],"explicitBounds":[10,30],"min":20,"max":20}]}},{"name":"response_size","unit":"1","description":"","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"1","sum":3,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[0,1,0],"explicitBounds":[1,5],"min":3,"max":3}]}}]}]}]}"#
         );
+    }
+
+    #[test]
+    fn test_metric_histogram_buckets_per_name() {
+        let recorder = OtlpRecorder::new(
+            "otlp-metrics",
+            "1",
+            "test_metric_histogram_buckets_per_name",
+        )
+        .scope("test", "1")
+        .clock(test_clock())
+        .metric_histogram_buckets("request_time", vec![0.0])
+        .metric_histogram_buckets("request_time", vec![10.0, 30.0])
+        .metric_histogram_buckets("payload_size", vec![1024.0, 65536.0, 1048576.0]);
+        let _guard = set_default_local_recorder(&recorder);
+
+        histogram!("request_time").record(20);
+        histogram!("payload_size").record(2048);
 
         assert_eq!(
-            recorder.to_json(Duration::from_millis(101).into()),
-            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_output_only_changed_values"}}]},"scopeMetrics":[{"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":1,"startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449405000000,"attributes":[{"key":"label1","value":{"stringValue":"label_value1"}}]}]}}]}]}]}"#
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_metric_histogram_buckets_per_name"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"payload_size","unit":"1","description":"","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"1","sum":2048,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[0,1,0,0],"explicitBounds":[1024,65536,1048576],"min":2048,"max":2048}]}},{"name":"request_time","unit":"1","description":"","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"1","sum":20,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[0,1,0],"explicitBounds":[10,30],"min":20,"max":20}]}}]}]}]}"#
         );
+    }
+
+    #[test]
+    fn test_buckets_label_overrides_default_buckets() {
+        let recorder = OtlpRecorder::new(
+            "otlp-metrics",
+            "1",
+            "test_buckets_label_overrides_default_buckets",
+        )
+        .scope("test", "1")
+        .clock(test_clock())
+        .histogram_buckets(vec![1.0, 5.0])
+        .metric_histogram_buckets("request_time", vec![10.0, 30.0]);
+        let _guard = set_default_local_recorder(&recorder);
+
+        histogram!("request_time", "buckets" => "100").record(20);
 
         assert_eq!(
-            recorder.to_json(Duration::from_millis(99).into()),
-            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_output_only_changed_values"}}]},"scopeMetrics":[{"metrics":[]}]}]}"#
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_buckets_label_overrides_default_buckets"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"request_time","unit":"1","description":"","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"1","sum":20,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[1,0],"explicitBounds":[100],"min":20,"max":20}]}}]}]}]}"#
         );
+    }
+
+    #[test]
+    fn test_buckets_label_not_exported() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_buckets_label_not_exported")
+            .clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        histogram!("request_time", "buckets" => "10,30", "method" => "GET").record(20);
+
+        let output = recorder.to_json();
+        assert!(!output.contains(r#""key":"buckets""#));
+        assert!(output.contains(
+            r#""attributes":[{"key":"method","value":{"stringValue":"GET"}}],"droppedAttributesCount":0,"flags":0,"bucketCounts":[0,1,0],"explicitBounds":[10,30]"#
+        ));
+    }
+
+    #[test]
+    fn test_global_labels() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_global_labels")
+            .scope("test", "1")
+            .clock(test_clock())
+            .global_label("region", "eu-west-1")
+            .global_label("label1", "global_value");
+        let _guard = set_default_local_recorder(&recorder);
 
         counter!("test_counter", "label1" => "label_value1").increment(1);
 
         assert_eq!(
-            recorder.to_json(Duration::from_secs(99).into()),
-            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_output_only_changed_values"}}]},"scopeMetrics":[{"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":2,"startTimeUnixNano":1739394449305000000,"timeUnixNano":1739394449705000000,"attributes":[{"key":"label1","value":{"stringValue":"label_value1"}}]}]}}]}]}]}"#
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_global_labels"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[{"key":"label1","value":{"stringValue":"label_value1"}},{"key":"region","value":{"stringValue":"eu-west-1"}}],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_double_counter() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_double_counter")
+            .scope("test", "1")
+            .clock(test_clock())
+            .double_counter("cost");
+        let _guard = set_default_local_recorder(&recorder);
+
+        gauge!("cost").increment(0.25);
+        gauge!("cost").increment(1.5);
+        gauge!("cost").decrement(1);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_double_counter"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"cost","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asDouble":1.75,"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_large_integers_as_strings() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_large_integers_as_strings")
+            .clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter").absolute(u64::MAX);
+
+        assert!(recorder
+            .to_json()
+            .contains(r#""asInt":"18446744073709551615""#));
+    }
+
+    #[test]
+    fn test_exemplars() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_exemplars")
+            .scope("test", "1")
+            .clock(test_clock())
+            .exemplars(true);
+        let _guard = set_default_local_recorder(&recorder);
+
+        histogram!("request_time", "buckets" => "10,30").record(5);
+        set_trace_context([1; 16], [2; 8]);
+        histogram!("request_time", "buckets" => "10,30").record(20);
+        counter!("requests").increment(1);
+        clear_trace_context();
+        counter!("requests").increment(1);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_exemplars"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"request_time","unit":"1","description":"","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"2","sum":25,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[1,1,0],"explicitBounds":[10,30],"min":5,"max":20,"exemplars":[{"timeUnixNano":1739394449205000000,"asDouble":20,"traceId":"01010101010101010101010101010101","spanId":"0202020202020202"}]}]}},{"name":"requests","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"2","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0,"exemplars":[{"timeUnixNano":1739394449205000000,"asDouble":1,"traceId":"01010101010101010101010101010101","spanId":"0202020202020202"}]}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_clear() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_clear").clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        describe_counter!("test_counter", "Test counter");
+        counter!("test_counter").increment(1);
+        recorder.clear();
+
+        assert!(!recorder.to_json().contains("test_counter"));
+
+        counter!("test_counter").increment(2);
+        let output = recorder.to_json();
+        assert!(output.contains(r#""name":"test_counter","unit":"1","description":"""#));
+        assert!(output.contains(r#""asInt":"2""#));
+    }
+
+    #[test]
+    fn test_reset_values() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_reset_values")
+            .scope("test", "1")
+            .clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        let counter = counter!("test_counter");
+        counter.increment(5);
+        gauge!("test_gauge").set(10);
+        histogram!("test_histogram").record(3);
+        recorder.reset_values();
+        counter.increment(1);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_reset_values"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}},{"name":"test_gauge","unit":"1","description":"","gauge":{"dataPoints":[{"asDouble":10,"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}},{"name":"test_histogram","unit":"1","description":"","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"0","sum":0,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[],"explicitBounds":[]}]}}]}]}]}"#
         );
     }
+
+    #[test]
+    fn test_evict_stale() {
+        let clock = test_clock();
+        let recorder =
+            OtlpRecorder::new("otlp-metrics", "1", "test_evict_stale").clock(clock.clone());
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("stale_counter").increment(1);
+        clock.set(Duration::from_millis(1739394459205));
+        counter!("fresh_counter").increment(1);
+        recorder.evict_stale(Duration::from_secs(5));
+
+        let output = recorder.to_json();
+        assert!(!output.contains("stale_counter"));
+        assert!(output.contains("fresh_counter"));
+
+        counter!("stale_counter").increment(1);
+        assert!(recorder
+            .to_json()
+            .contains(r#""name":"stale_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1""#));
+    }
+
+    #[test]
+    fn test_ttl_evicts_on_export() {
+        let clock = test_clock();
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_ttl_evicts_on_export")
+            .clock(clock.clone())
+            .ttl(Duration::from_secs(5));
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("stale_counter").increment(1);
+        assert!(recorder.to_json().contains("stale_counter"));
+        clock.set(Duration::from_millis(1739394459205));
+
+        assert!(!recorder.to_json().contains("stale_counter"));
+    }
+
+    #[test]
+    fn test_max_series() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_max_series")
+            .clock(test_clock())
+            .max_series(2);
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter", "request" => "1").increment(1);
+        gauge!("test_gauge").set(1);
+        counter!("test_counter", "request" => "2").increment(1);
+        histogram!("test_histogram").record(1);
+        counter!("test_counter", "request" => "1").increment(1);
+
+        assert_eq!(recorder.dropped_series(), 2);
+        let output = recorder.to_json();
+        assert!(output.contains(r#""asInt":"2""#));
+        assert!(output.contains("test_gauge"));
+        assert!(!output.contains(r#""stringValue":"2""#));
+        assert!(!output.contains("test_histogram"));
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = Arc::new(ManualClock::new(Duration::from_secs(1739394449)));
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_manual_clock")
+            .scope("test", "1")
+            .clock(clock.clone());
+        let _guard = set_default_local_recorder(&recorder);
+
+        let counter = counter!("test_counter");
+        clock.advance(Duration::from_secs(1));
+        counter.increment(1);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_manual_clock"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449000000000,"timeUnixNano":1739394450000000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(
+            recorder.to_json_since(Duration::from_secs(10)),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_manual_clock"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_unwritten_metrics_report_start_time() {
+        let clock = test_clock();
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_unwritten_metrics")
+            .scope("test", "1")
+            .clock(clock.clone());
+        let _guard = set_default_local_recorder(&recorder);
+
+        let _gauge = gauge!("test_gauge");
+        let _counter = counter!("test_counter");
+        let _histogram = histogram!("test_histogram");
+        clock.advance(Duration::from_millis(100));
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_unwritten_metrics"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"0","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}},{"name":"test_gauge","unit":"1","description":"","gauge":{"dataPoints":[{"asDouble":0,"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}},{"name":"test_histogram","unit":"1","description":"","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"0","sum":0,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[],"explicitBounds":[]}]}}]}]}]}"#
+        );
+        // never updated within the period
+        assert!(!recorder
+            .to_json_since(Duration::from_secs(10))
+            .contains("test_gauge"));
+    }
+
+    #[test]
+    fn test_skip_unwritten() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_skip_unwritten")
+            .scope("test", "1")
+            .clock(test_clock())
+            .skip_unwritten(true);
+        let _guard = set_default_local_recorder(&recorder);
+
+        let _gauge = gauge!("test_gauge");
+        let _histogram = histogram!("test_histogram");
+        counter!("test_counter").increment(1);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_skip_unwritten"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_snapshot")
+            .clock(test_clock())
+            .global_label("region", "eu");
+        let _guard = set_default_local_recorder(&recorder);
+
+        describe_counter!("test_counter", "requests handled");
+        counter!("test_counter", "path" => "/").increment(3);
+        histogram!("test_histogram", "buckets" => "1,10").record(5);
+
+        let snapshot = recorder.snapshot();
+
+        assert_eq!(
+            snapshot[0],
+            MetricSnapshot {
+                name: "test_counter".to_string(),
+                unit: "1".to_string(),
+                description: "requests handled".to_string(),
+                labels: vec![
+                    ("path".to_string(), "/".to_string()),
+                    ("region".to_string(), "eu".to_string())
+                ],
+                start_time: 1739394449205000000,
+                time: 1739394449205000000,
+                value: SnapshotValue::Counter(3),
+            }
+        );
+        assert_eq!(
+            snapshot[1].value,
+            SnapshotValue::Histogram {
+                count: 1,
+                sum: 5.0,
+                min: Some(5.0),
+                max: Some(5.0),
+                bucket_counts: vec![0, 1, 0],
+                explicit_bounds: vec![1.0, 10.0],
+            }
+        );
+    }
+
+    #[test]
+    fn test_install_recorder_twice() {
+        let recorder =
+            install_recorder_or_existing("otlp-metrics", "1", "test_install_recorder").unwrap();
+        let existing = install_recorder_or_existing("other", "2", "other").unwrap();
+        assert!(Arc::ptr_eq(&recorder, &existing));
+
+        let Err(error) = install_recorder("otlp-metrics", "1", "test_install_recorder") else {
+            panic!("Recorder installed twice");
+        };
+        assert_eq!(error.into_inner().instance_id, "test_install_recorder");
+        assert!(Arc::ptr_eq(
+            &recorder,
+            &install_recorder_or_existing("other", "2", "other").unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_summary() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_summary")
+            .scope("test", "1")
+            .clock(test_clock())
+            .summary("test_summary", vec![0.5, 0.9]);
+        let _guard = set_default_local_recorder(&recorder);
+
+        for value in 1..=100 {
+            histogram!("test_summary", "path" => "/").record(value);
+        }
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_summary"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_summary","unit":"1","description":"","summary":{"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"100","sum":5050,"quantileValues":[{"quantile":0.5,"value":50.9111015743002},{"quantile":0.9,"value":89.13032933635917}],"attributes":[{"key":"path","value":{"stringValue":"/"}}],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+        let SnapshotValue::Summary { quantiles, .. } = &recorder.snapshot()[0].value else {
+            panic!("Summary expected");
+        };
+        // the median of 1..=100 within 1% relative error
+        assert!((quantiles[0].1 - 50.5).abs() <= 0.505);
+        assert!(recorder
+            .to_prometheus()
+            .contains("# TYPE test_summary summary\ntest_summary{path=\"/\",quantile=\"0.5\"}"));
+    }
+
+    #[test]
+    fn test_namespace() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_namespace")
+            .scope("test", "1")
+            .clock(test_clock())
+            .namespace("svc");
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter").increment(1);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_namespace"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"svc_test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+        assert_eq!(
+            recorder.to_prometheus(),
+            "# TYPE svc_test_counter_total counter\nsvc_test_counter_total 1\n"
+        );
+    }
+
+    #[test]
+    fn test_label_filters() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_label_filters")
+            .scope("test", "1")
+            .clock(test_clock())
+            .global_label("region", "eu")
+            .deny_label("user_id");
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter", "path" => "/", "user_id" => "42").increment(1);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_label_filters"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[{"key":"path","value":{"stringValue":"/"}},{"key":"region","value":{"stringValue":"eu"}}],"droppedAttributesCount":1,"flags":0}]}}]}]}]}"#
+        );
+
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_label_filters")
+            .scope("test", "1")
+            .clock(test_clock())
+            .global_label("region", "eu")
+            .allow_label("path");
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter", "path" => "/", "user_id" => "42").increment(1);
+
+        assert_eq!(
+            recorder.snapshot()[0].labels,
+            [("path".to_string(), "/".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_dropped_attributes_count() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_dropped_attributes_count")
+            .clock(test_clock())
+            .global_label("host", "a")
+            .deny_label("user_id")
+            .deny_label("host");
+        let _guard = set_default_local_recorder(&recorder);
+
+        gauge!("test_gauge", "user_id" => "42").set(1);
+        histogram!("test_histogram", "path" => "/").record(1);
+
+        let output = ::json::parse(&recorder.to_json()).unwrap();
+        let metrics = &output["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        let gauge = &metrics[0]["gauge"]["dataPoints"][0];
+        assert_eq!(gauge["droppedAttributesCount"], 2);
+        assert_eq!(gauge["flags"], 0);
+        assert!(gauge["attributes"].is_empty());
+        let histogram = &metrics[1]["histogram"]["dataPoints"][0];
+        assert_eq!(histogram["droppedAttributesCount"], 1);
+        assert_eq!(histogram["attributes"][0]["key"], "path");
+    }
+
+    #[test]
+    fn test_non_finite_values() {
+        for (policy, gauge_value, histogram_sum) in [
+            (NonFiniteValues::Zero, Some(0.0), Some(0.0)),
+            (NonFiniteValues::Clamp, Some(0.0), Some(f64::MAX)),
+            (NonFiniteValues::Skip, None, None),
+        ] {
+            let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_non_finite_values")
+                .clock(test_clock())
+                .non_finite_values(policy);
+            let _guard = set_default_local_recorder(&recorder);
+
+            gauge!("test_gauge").set(f64::NAN);
+            histogram!("test_histogram").record(f64::INFINITY);
+            counter!("test_counter").increment(1);
+
+            let output = ::json::parse(&recorder.to_json()).unwrap();
+            let metrics = &output["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+            let find = |name: &str| metrics.members().find(|m| m["name"] == name);
+            assert_eq!(
+                find("test_gauge").map(|m| m["gauge"]["dataPoints"][0]["asDouble"].as_f64()),
+                gauge_value.map(Some),
+                "{policy:?}"
+            );
+            let histogram = find("test_histogram").map(|m| &m["histogram"]["dataPoints"][0]);
+            assert_eq!(
+                histogram.map(|p| p["sum"].as_f64()),
+                histogram_sum.map(Some),
+                "{policy:?}"
+            );
+            if let Some(point) = histogram {
+                assert_eq!(point["max"].as_f64(), histogram_sum);
+            }
+            assert!(find("test_counter").is_some());
+        }
+    }
+
+    #[test]
+    fn test_exponential_histogram() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_exponential_histogram")
+            .scope("test", "1")
+            .clock(test_clock())
+            .exponential_histogram("test_histogram");
+        let _guard = set_default_local_recorder(&recorder);
+
+        for value in [0.0, 4.0, 4.0, -2.0] {
+            histogram!("test_histogram").record(value);
+        }
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_exponential_histogram"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_histogram","unit":"1","description":"","exponentialHistogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"4","sum":6,"scale":20,"zeroCount":"1","positive":{"offset":2097151,"bucketCounts":[2]},"negative":{"offset":1048575,"bucketCounts":[1]},"attributes":[],"droppedAttributesCount":0,"flags":0,"min":-2,"max":4}]}}]}]}]}"#
+        );
+        assert_eq!(
+            recorder.to_prometheus(),
+            "# TYPE test_histogram histogram\ntest_histogram_bucket{le=\"-1.9999986779271097\"} 1\ntest_histogram_bucket{le=\"0\"} 2\ntest_histogram_bucket{le=\"4\"} 4\ntest_histogram_bucket{le=\"+Inf\"} 4\ntest_histogram_sum 6\ntest_histogram_count 4\n"
+        );
+    }
+
+    #[test]
+    fn test_output_sorted_by_name_and_labels() {
+        let recorder =
+            OtlpRecorder::new("otlp-metrics", "1", "test_output_sorted_by_name_and_labels")
+                .clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("c_counter").increment(1);
+        counter!("a_counter", "method" => "POST", "code" => "200").increment(1);
+        gauge!("b_gauge").set(1);
+        counter!("a_counter", "method" => "GET", "code" => "200").increment(1);
+        counter!("a_counter", "code" => "200", "method" => "DELETE").increment(1);
+
+        let output = ::json::parse(&recorder.to_json()).unwrap();
+        let metrics = &output["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        let series: Vec<(String, Vec<String>)> = metrics
+            .members()
+            .flat_map(|metric| {
+                let name = metric["name"].to_string();
+                let data = if metric["sum"].is_null() {
+                    &metric["gauge"]
+                } else {
+                    &metric["sum"]
+                };
+                data["dataPoints"]
+                    .members()
+                    .map(|point| {
+                        let attributes = point["attributes"]
+                            .members()
+                            .map(|a| format!("{}={}", a["key"], a["value"]["stringValue"]))
+                            .collect();
+                        (name.clone(), attributes)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(
+            series,
+            vec![
+                (
+                    "a_counter".to_string(),
+                    vec!["code=200".to_string(), "method=DELETE".to_string()]
+                ),
+                (
+                    "a_counter".to_string(),
+                    vec!["method=GET".to_string(), "code=200".to_string()]
+                ),
+                (
+                    "a_counter".to_string(),
+                    vec!["method=POST".to_string(), "code=200".to_string()]
+                ),
+                ("b_gauge".to_string(), vec![]),
+                ("c_counter".to_string(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_metric_unit() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_metric_unit")
+            .scope("test", "1")
+            .clock(test_clock())
+            .metric_unit("throughput", "By/s");
+        let _guard = set_default_local_recorder(&recorder);
+
+        describe_gauge!("throughput", Unit::Bytes, "bytes sent per second");
+        gauge!("throughput").set(1024);
+        describe_gauge!("queue_size", Unit::Count, "queued items");
+        gauge!("queue_size").set(3);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_metric_unit"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"queue_size","unit":"","description":"queued items","gauge":{"dataPoints":[{"asDouble":3,"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}},{"name":"throughput","unit":"By/s","description":"bytes sent per second","gauge":{"dataPoints":[{"asDouble":1024,"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_schema_urls() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_schema_urls")
+            .scope("test", "1")
+            .clock(test_clock())
+            .resource_schema_url("https://opentelemetry.io/schemas/1.26.0")
+            .scope_schema_url("https://opentelemetry.io/schemas/1.24.0");
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter").increment(1);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_schema_urls"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"test_counter","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asInt":"1","startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"attributes":[],"droppedAttributesCount":0,"flags":0}]}}],"schemaUrl":"https://opentelemetry.io/schemas/1.24.0"}],"schemaUrl":"https://opentelemetry.io/schemas/1.26.0"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_concurrent_registration() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_concurrent_registration")
+            .clock(test_clock());
+
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    let _guard = set_default_local_recorder(&recorder);
+                    for i in 0..500 {
+                        counter!("concurrent_counter", "index" => (i % 50).to_string())
+                            .increment(1);
+                        histogram!("concurrent_histogram").record(1);
+                    }
+                });
+            }
+        });
+
+        let snapshot = recorder.snapshot();
+        assert_eq!(snapshot.len(), 51);
+        let mut total = 0;
+        for metric in snapshot {
+            match metric.value {
+                SnapshotValue::Counter(value) => {
+                    assert_eq!(value, 80, "{}", metric.name);
+                    total += value;
+                }
+                SnapshotValue::Histogram { count, .. } => assert_eq!(count, 4000),
+                value => panic!("unexpected {value:?}"),
+            }
+        }
+        assert_eq!(total, 4000);
+    }
+
+    #[test]
+    fn test_registration_during_serialization() {
+        use std::sync::mpsc::{channel, Receiver, Sender};
+
+        /// Blocks the first write until a metric was registered
+        struct SlowWriter {
+            started: Sender<()>,
+            registered: Receiver<()>,
+            output: Vec<u8>,
+        }
+
+        impl std::io::Write for SlowWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                if self.output.is_empty() {
+                    self.started.send(()).unwrap();
+                    self.registered
+                        .recv_timeout(Duration::from_secs(5))
+                        .expect("registration blocked by serialization");
+                }
+                self.output.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let recorder = OtlpRecorder::new(
+            "otlp-metrics",
+            "1",
+            "test_registration_during_serialization",
+        )
+        .clock(test_clock());
+        {
+            let _guard = set_default_local_recorder(&recorder);
+            counter!("early_counter").increment(1);
+        }
+        let (started, started_rx) = channel();
+        let (registered, registered_rx) = channel();
+        let mut writer = SlowWriter {
+            started,
+            registered: registered_rx,
+            output: Vec::new(),
+        };
+
+        std::thread::scope(|s| {
+            let recorder = &recorder;
+            s.spawn(move || {
+                started_rx.recv().unwrap();
+                let _guard = set_default_local_recorder(recorder);
+                counter!("late_counter").increment(1);
+                registered.send(()).unwrap();
+            });
+            recorder.to_writer(&mut writer).unwrap();
+        });
+
+        let output = String::from_utf8(writer.output).unwrap();
+        assert!(output.contains(r#""name":"early_counter""#));
+        assert!(!output.contains(r#""name":"late_counter""#));
+        assert!(recorder.to_json().contains(r#""name":"late_counter""#));
+    }
+
+    #[test]
+    fn test_read_values() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_read_values")
+            .clock(test_clock())
+            .summary("summary_time", vec![0.5]);
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("errors", "kind" => "io", "code" => "500").increment(3);
+        gauge!("temperature").set(21.5);
+        histogram!("request_time").record(1);
+        histogram!("request_time").record(2);
+        histogram!("summary_time").record(1);
+
+        assert_eq!(
+            recorder.get_counter("errors", &[("code", "500"), ("kind", "io")]),
+            Some(3)
+        );
+        assert_eq!(recorder.get_counter("errors", &[("kind", "io")]), None);
+        assert_eq!(recorder.get_counter("missing", &[]), None);
+        assert_eq!(recorder.get_counter("temperature", &[]), None);
+
+        assert_eq!(recorder.get_gauge("temperature", &[]), Some(21.5));
+        assert_eq!(recorder.get_gauge("temperature", &[("room", "1")]), None);
+        assert_eq!(recorder.get_gauge("missing", &[]), None);
+
+        assert_eq!(recorder.get_histogram_count("request_time", &[]), Some(2));
+        assert_eq!(recorder.get_histogram_count("summary_time", &[]), Some(1));
+        assert_eq!(recorder.get_histogram_count("missing", &[]), None);
+        assert_eq!(recorder.get_histogram_count("temperature", &[]), None);
+    }
+
+    #[test]
+    fn test_sanitize_names() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_sanitize_names")
+            .clock(test_clock())
+            .sanitize_names('_');
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("http.server.duration", "http.method" => "GET", "valid_key" => "a.b").increment(1);
+        gauge!("2xx-Responses").set(1);
+
+        let output = recorder.to_json();
+        assert!(
+            output.contains(r#""name":"http_server_duration""#),
+            "{output}"
+        );
+        assert!(output.contains(r#""key":"http_method","value":{"stringValue":"GET"}"#));
+        assert!(output.contains(r#""key":"valid_key","value":{"stringValue":"a.b"}"#));
+        assert!(output.contains(r#""name":"_xx_Responses""#), "{output}");
+
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_sanitize_names")
+            .clock(test_clock())
+            .namespace("App")
+            .sanitize_names('-')
+            .lowercase_names();
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("HTTP.Requests", "Http.Method" => "GET").increment(1);
+
+        let snapshot = recorder.snapshot();
+        assert_eq!(snapshot[0].name, "app_http-requests");
+        assert_eq!(
+            snapshot[0].labels,
+            vec![("http-method".to_string(), "GET".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_described_histogram_buckets() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_described_histogram_buckets")
+            .scope("test", "1")
+            .clock(test_clock())
+            .metric_histogram_buckets("request_time", vec![1.0]);
+        let _guard = set_default_local_recorder(&recorder);
+
+        describe_histogram!(
+            "request_time",
+            Unit::Milliseconds,
+            "Request time [buckets=10,30]"
+        );
+        histogram!("request_time").record(20);
+        histogram!("request_time", "buckets" => "100").record(20);
+
+        assert_eq!(
+            recorder.to_json(),
+            r#"{"resourceMetrics":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"otlp-metrics"}},{"key":"service.version","value":{"stringValue":"1"}},{"key":"service.instance.id","value":{"stringValue":"test_described_histogram_buckets"}}]},"scopeMetrics":[{"scope":{"name":"test","version":"1"},"metrics":[{"name":"request_time","unit":"ms","description":"Request time","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"1","sum":20,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[0,1,0],"explicitBounds":[10,30],"min":20,"max":20}]}},{"name":"request_time","unit":"ms","description":"Request time","histogram":{"aggregationTemporality":2,"dataPoints":[{"startTimeUnixNano":1739394449205000000,"timeUnixNano":1739394449205000000,"count":"1","sum":20,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[1,0],"explicitBounds":[100],"min":20,"max":20}]}}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_hostname() {
+        let name = hostname();
+        assert!(!name.is_empty());
+        assert_eq!(name, name.trim());
+    }
+
+    #[test]
+    fn test_to_json_matches_to_json_since_large_period() {
+        let recorder =
+            OtlpRecorder::new("otlp-metrics", "1", "test_to_json_since").clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter", "label1" => "label_value1").increment(1);
+        gauge!("test_gauge").set(10);
+        histogram!("test_histogram", "buckets" => "10,30").record(10);
+
+        assert_eq!(
+            recorder.to_json(),
+            recorder.to_json_since(Duration::from_secs(u32::MAX.into()))
+        );
+    }
+
+    #[test]
+    fn test_json_transform() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_json_transform")
+            .clock(test_clock())
+            .json_transform(|json| format!(r#"{{"data":{json}}}"#));
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter").increment(1);
+
+        let json = recorder.to_json();
+        let parsed = ::json::parse(&json).unwrap();
+        assert_eq!(
+            parsed["data"]["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0]["name"],
+            "test_counter"
+        );
+        let mut output = Vec::new();
+        recorder.to_writer(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), json);
+    }
+
+    #[test]
+    fn test_histogram_infinite_bound_label() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_histogram_infinite_bound")
+            .clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        histogram!("test_histogram", "buckets" => "10,30,+Inf").record(20);
+        histogram!("test_histogram", "buckets" => "10,30,+Inf").record(100);
+
+        let json = recorder.to_json();
+        assert!(
+            json.contains(r#""bucketCounts":[0,1,1],"explicitBounds":[10,30]"#),
+            "{json}"
+        );
+    }
+
+    #[test]
+    fn test_recorder_to_openmetrics() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_recorder_to_openmetrics")
+            .clock(test_clock())
+            .summary("response_time", vec![0.5]);
+        let _guard = set_default_local_recorder(&recorder);
+
+        describe_counter!("http.requests", "Handled \"http\" requests");
+        counter!("http.requests", "method" => "GET").increment(3);
+        describe_gauge!("memory_usage_bytes", Unit::Bytes, "Memory used");
+        gauge!("memory_usage_bytes").set(1024.5);
+        describe_histogram!("request_time_seconds", Unit::Seconds, "Request time");
+        histogram!("request_time_seconds", "buckets" => "0.1,1").record(0.05);
+        histogram!("request_time_seconds", "buckets" => "0.1,1").record(2);
+        histogram!("response_time").record(10);
+
+        assert_eq!(
+            recorder.to_openmetrics(),
+            r#"# HELP http_requests Handled \"http\" requests
+# TYPE http_requests counter
+http_requests_total{method="GET"} 3
+http_requests_created{method="GET"} 1739394449.205
+# HELP memory_usage_bytes Memory used
+# TYPE memory_usage_bytes gauge
+# UNIT memory_usage_bytes bytes
+memory_usage_bytes 1024.5
+# HELP request_time_seconds Request time
+# TYPE request_time_seconds histogram
+# UNIT request_time_seconds seconds
+request_time_seconds_bucket{le="0.1"} 1
+request_time_seconds_bucket{le="1"} 1
+request_time_seconds_bucket{le="+Inf"} 2
+request_time_seconds_sum 2.05
+request_time_seconds_count 2
+request_time_seconds_created 1739394449.205
+# TYPE response_time summary
+response_time{quantile="0.5"} 10
+response_time_sum 10
+response_time_count 1
+response_time_created 1739394449.205
+# EOF
+"#
+        );
+    }
+
+    #[test]
+    fn test_delta_counter_keeps_increments_during_export() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_delta_counter_export")
+            .clock(test_clock())
+            .aggregation_temporality(AggregationTemporality::Delta);
+        let counter = {
+            let _guard = set_default_local_recorder(&recorder);
+            counter!("test_counter")
+        };
+        let exported_value = |snapshot: Vec<MetricSnapshot>| {
+            snapshot
+                .iter()
+                .map(|m| match m.value {
+                    SnapshotValue::Counter(v) => v,
+                    _ => 0,
+                })
+                .sum::<u64>()
+        };
+
+        let mut exported = 0;
+        std::thread::scope(|s| {
+            let writers: Vec<_> = (0..4)
+                .map(|_| {
+                    let counter = counter.clone();
+                    s.spawn(move || {
+                        for _ in 0..200_000 {
+                            counter.increment(1);
+                        }
+                    })
+                })
+                .collect();
+            while writers.iter().any(|w| !w.is_finished()) {
+                exported += exported_value(recorder.snapshot());
+            }
+        });
+        exported += exported_value(recorder.snapshot());
+
+        assert_eq!(exported, 800_000);
+    }
+
+    #[test]
+    fn test_non_monotonic_sum() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_non_monotonic_sum")
+            .clock(test_clock())
+            .non_monotonic("queue_size");
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("queue_size").absolute(5);
+        counter!("requests").increment(1);
+
+        let json = ::json::parse(&recorder.to_json()).unwrap();
+        let metrics = &json["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics[0]["name"], "queue_size");
+        assert_eq!(metrics[0]["sum"]["isMonotonic"], false);
+        assert_eq!(metrics[1]["name"], "requests");
+        assert_eq!(metrics[1]["sum"]["isMonotonic"], true);
+        assert_eq!(
+            recorder.to_prometheus(),
+            "# TYPE queue_size gauge\nqueue_size 5\n# TYPE requests_total counter\nrequests_total 1\n"
+        );
+    }
+
+    #[test]
+    fn test_up_down_counter() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_up_down_counter")
+            .clock(test_clock())
+            .up_down_counter("active_connections");
+        let _guard = set_default_local_recorder(&recorder);
+
+        gauge!("active_connections").increment(3);
+        gauge!("active_connections").decrement(1);
+        gauge!("active_connections").increment(-0.5);
+
+        let json = ::json::parse(&recorder.to_json()).unwrap();
+        let sum = &json["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0]["sum"];
+        assert_eq!(sum["isMonotonic"], false);
+        assert_eq!(sum["dataPoints"][0]["asDouble"], 1.5);
+
+        gauge!("active_connections").set(-2);
+        assert_eq!(recorder.get_gauge("active_connections", &[]), Some(-2.0));
+    }
+
+    #[test]
+    fn test_time_precision() {
+        let clock = test_clock();
+        clock.advance(Duration::from_nanos(123_456));
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_time_precision")
+            .time_precision(TimePrecision::Milliseconds)
+            .clock(clock);
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter").increment(1);
+
+        let snapshot = recorder.snapshot();
+        assert_eq!(snapshot[0].start_time, 1_739_394_449_205_000_000);
+        assert_eq!(snapshot[0].time, 1_739_394_449_205_000_000);
+    }
+
+    #[test]
+    fn test_timestamps_do_not_go_backwards() {
+        let clock = test_clock();
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_timestamps_backwards")
+            .clock(clock.clone());
+        let _guard = set_default_local_recorder(&recorder);
+
+        clock.advance(Duration::from_secs(10));
+        counter!("test_counter").increment(1);
+        gauge!("test_gauge").set(1);
+        let first = recorder.snapshot();
+
+        clock.set(Duration::from_millis(1739394440000));
+        counter!("test_counter").increment(1);
+        gauge!("test_gauge").set(2);
+        let second = recorder.snapshot();
+
+        for (first, second) in first.iter().zip(&second) {
+            assert!(second.time >= first.time, "{}", first.name);
+            assert!(second.time >= second.start_time, "{}", first.name);
+        }
+        assert_eq!(second[0].value, SnapshotValue::Counter(2));
+    }
+
+    #[test]
+    fn test_duplicate_labels() {
+        let attributes = |recorder: &OtlpRecorder| {
+            let json = ::json::parse(&recorder.to_json()).unwrap();
+            json["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0]["sum"]["dataPoints"][0]
+                ["attributes"]
+                .clone()
+        };
+
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_duplicate_labels");
+        {
+            let _guard = set_default_local_recorder(&recorder);
+            counter!("test_counter", "method" => "GET", "method" => "POST").increment(1);
+        }
+        let last = attributes(&recorder);
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0]["value"]["stringValue"], "POST");
+
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_duplicate_labels")
+            .duplicate_labels(DuplicateLabels::First);
+        {
+            let _guard = set_default_local_recorder(&recorder);
+            counter!("test_counter", "method" => "GET", "method" => "POST").increment(1);
+        }
+        let first = attributes(&recorder);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0]["value"]["stringValue"], "GET");
+    }
+
+    #[test]
+    fn test_to_json_value() {
+        let recorder =
+            OtlpRecorder::new("otlp-metrics", "1", "test_to_json_value").clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter", "label1" => "label_value1").increment(1);
+        histogram!("test_histogram", "buckets" => "10,30").record(10);
+
+        let value = recorder.to_json_value();
+        let metrics = &value["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics[0]["name"], "test_counter");
+        assert_eq!(metrics[1]["histogram"]["dataPoints"][0]["count"], "1");
+        assert_eq!(::json::stringify(value), recorder.to_json());
+    }
+
+    #[test]
+    fn test_to_json_value_resets_delta_values() {
+        let clock = test_clock();
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_to_json_value_delta")
+            .clock(clock.clone())
+            .aggregation_temporality(AggregationTemporality::Delta);
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter").increment(5);
+
+        let value = recorder.to_json_value();
+        let metrics = &value["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics[0]["sum"]["dataPoints"][0]["asInt"], "5");
+
+        clock.advance(Duration::from_secs(1));
+        counter!("test_counter").increment(2);
+
+        assert!(recorder.to_json().contains(r#""asInt":"2""#));
+    }
+
+    #[test]
+    fn test_to_json_pretty() {
+        let recorder =
+            OtlpRecorder::new("otlp-metrics", "1", "test_to_json_pretty").clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter", "label1" => "label_value1").increment(1);
+        gauge!("test_gauge").set(10);
+
+        let pretty = recorder.to_json_pretty(2);
+        assert!(pretty.starts_with("{\n  \"resourceMetrics\": ["));
+        assert_eq!(
+            ::json::parse(&pretty).unwrap(),
+            ::json::parse(&recorder.to_json()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_to_json_pretty_keeps_delta_values() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_to_json_pretty_delta")
+            .clock(test_clock())
+            .aggregation_temporality(AggregationTemporality::Delta);
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter").increment(5);
+
+        assert!(recorder.to_json_pretty(2).contains(r#""asInt": "5""#));
+        assert!(recorder.to_json().contains(r#""asInt":"5""#));
+    }
+
+    #[test]
+    fn test_trace_id_attribute() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_trace_id_attribute")
+            .clock(test_clock())
+            .trace_id_attribute("trace_id", || {
+                Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string())
+            });
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter", "label1" => "label_value1").increment(1);
+        gauge!("test_gauge").set(10);
+        histogram!("test_histogram", "buckets" => "10,30").record(10);
+
+        let value = ::json::parse(&recorder.to_json()).unwrap();
+        let metrics = &value["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(
+            metrics[0]["sum"]["dataPoints"][0]["attributes"].dump(),
+            r#"[{"key":"label1","value":{"stringValue":"label_value1"}},{"key":"trace_id","value":{"stringValue":"4bf92f3577b34da6a3ce929d0e0e4736"}}]"#
+        );
+        for (metric, kind) in [(&metrics[1], "gauge"), (&metrics[2], "histogram")] {
+            assert_eq!(
+                metric[kind]["dataPoints"][0]["attributes"].dump(),
+                r#"[{"key":"trace_id","value":{"stringValue":"4bf92f3577b34da6a3ce929d0e0e4736"}}]"#
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_buckets_label() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_invalid_buckets_label")
+            .clock(test_clock());
+        let _guard = set_default_local_recorder(&recorder);
+
+        histogram!("request_time", "buckets" => "10,,30").record(20);
+        histogram!("response_size", "buckets" => "abc").record(20);
+
+        let output = recorder.to_json();
+        assert_eq!(
+            output.matches(r#""count":"1","sum":20,"attributes":[],"droppedAttributesCount":0,"flags":0,"bucketCounts":[],"explicitBounds":[]"#).count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_recorder_from_env() {
+        let recorder = recorder_from_env(|name| {
+            (name == "OTEL_RESOURCE_ATTRIBUTES").then(|| {
+                "service.name=checkout,service.version=1.2.0,deployment.environment=prod%2Ceu"
+                    .to_string()
+            })
+        });
+        assert_eq!(recorder.name, "checkout");
+        assert_eq!(recorder.version, "1.2.0");
+        assert_eq!(recorder.instance_id, hostname());
+        assert_eq!(
+            recorder.resource_attributes,
+            vec![("deployment.environment".to_string(), "prod,eu".to_string())]
+        );
+
+        let recorder = recorder_from_env(|_| None);
+        assert_eq!(recorder.name, "unknown_service");
+        assert_eq!(recorder.version, "");
+
+        let recorder = recorder_from_env(|name| match name {
+            "OTEL_SERVICE_NAME" => Some("payments".to_string()),
+            "OTEL_RESOURCE_ATTRIBUTES" => {
+                Some("service.name=ignored,service.instance.id=pod-1".to_string())
+            }
+            _ => None,
+        });
+        assert_eq!(recorder.name, "payments");
+        assert_eq!(recorder.instance_id, "pod-1");
+        assert!(recorder.resource_attributes.is_empty());
+    }
+
+    #[test]
+    fn test_add_to_counter_f64() {
+        let recorder =
+            OtlpRecorder::new("otlp-metrics", "1", "test_add_to_counter_f64").clock(test_clock());
+
+        recorder.add_to_counter_f64("cost", &[("currency", "usd")], 1.5);
+        recorder.add_to_counter_f64("cost", &[("currency", "usd")], 1.5);
+        recorder.add_to_counter_f64("cost", &[("currency", "usd")], -1.0);
+
+        assert_eq!(
+            recorder.get_gauge("cost", &[("currency", "usd")]),
+            Some(3.0)
+        );
+        assert!(recorder.to_json().contains(
+            r#"{"name":"cost","unit":"1","description":"","sum":{"aggregationTemporality":2,"isMonotonic":true,"dataPoints":[{"asDouble":3,"#
+        ));
+    }
+
+    #[test]
+    fn test_detect_resources() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_detect_resources")
+            .resource_attributes(vec![("os.type".to_string(), "custom".to_string())])
+            .detect_resources(ResourceDetectors::default());
+
+        let attribute = |key: &str| {
+            recorder
+                .resource_attributes
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(
+            attribute("process.pid"),
+            Some(std::process::id().to_string().as_str())
+        );
+        assert_eq!(attribute("process.runtime.name"), Some("rust"));
+        assert_eq!(attribute("os.type"), Some("custom"));
+        assert_eq!(
+            attribute("host.name").map(str::to_string),
+            detect_hostname()
+        );
+
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_detect_resources")
+            .detect_resources(ResourceDetectors {
+                host: false,
+                process: true,
+                runtime: false,
+                os: false,
+            });
+        assert_eq!(
+            recorder.resource_attributes,
+            vec![("process.pid".to_string(), std::process::id().to_string())]
+        );
+    }
+
+    #[test]
+    fn test_process_start_time() {
+        let clock = test_clock();
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_process_start_time")
+            .process_start_time()
+            .clock(clock.clone())
+            .ttl(Duration::from_secs(5));
+        let _guard = set_default_local_recorder(&recorder);
+
+        clock.advance(Duration::from_secs(10));
+        counter!("test_counter").increment(1);
+        clock.advance(Duration::from_secs(3));
+        gauge!("test_gauge").set(1);
+
+        let json = ::json::parse(&recorder.to_json()).unwrap();
+        let metrics = &json["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(
+            metrics[0]["sum"]["dataPoints"][0]["startTimeUnixNano"],
+            1739394449205000000u64
+        );
+        assert_eq!(
+            metrics[1]["gauge"]["dataPoints"][0]["startTimeUnixNano"],
+            1739394449205000000u64
+        );
+        assert_eq!(
+            metrics[1]["gauge"]["dataPoints"][0]["timeUnixNano"],
+            1739394462205000000u64
+        );
+
+        // registered late and never written, kept for the ttl after registration
+        clock.advance(Duration::from_secs(3));
+        let _unwritten = gauge!("unwritten_gauge");
+        clock.advance(Duration::from_secs(4));
+        // evicted on the export, series registered afterwards start at the eviction
+        assert!(!recorder.to_json().contains("test_counter"));
+        counter!("test_counter").increment(1);
+
+        let json = ::json::parse(&recorder.to_json()).unwrap();
+        let metrics = &json["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics[0]["name"], "test_counter");
+        assert_eq!(
+            metrics[0]["sum"]["dataPoints"][0]["startTimeUnixNano"],
+            1739394469205000000u64
+        );
+        assert_eq!(metrics[1]["name"], "unwritten_gauge");
+        assert_eq!(
+            metrics[1]["gauge"]["dataPoints"][0]["startTimeUnixNano"],
+            1739394449205000000u64
+        );
+    }
+
+    #[test]
+    fn test_delta_summary_stays_cumulative() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_delta_summary")
+            .clock(test_clock())
+            .aggregation_temporality(AggregationTemporality::Delta)
+            .summary("test_summary", vec![0.5]);
+        let _guard = set_default_local_recorder(&recorder);
+
+        histogram!("test_summary").record(10);
+        histogram!("test_histogram").record(10);
+        recorder.to_json();
+        histogram!("test_summary").record(20);
+
+        assert_eq!(recorder.get_histogram_count("test_summary", &[]), Some(2));
+        assert_eq!(recorder.get_histogram_count("test_histogram", &[]), Some(0));
+        let json = ::json::parse(&recorder.to_json()).unwrap();
+        let summary = json["resourceMetrics"][0]["scopeMetrics"][0]["metrics"]
+            .members()
+            .find(|m| m["name"] == "test_summary")
+            .unwrap();
+        assert_eq!(summary["summary"]["dataPoints"][0]["count"], "2");
+    }
 }
@@ -1,19 +1,57 @@
 use core::{
     fmt::Display,
-    sync::atomic::{AtomicU64, Ordering},
+    hint::spin_loop,
+    sync::atomic::{fence, AtomicU64, Ordering},
 };
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use metrics::{CounterFn, GaugeFn, HistogramFn, Key, KeyName, Label, SharedString, Unit};
 
-use metrics::{CounterFn, GaugeFn, HistogramFn, Key, KeyName, SharedString, Unit};
+use tracing::error;
+
+use crate::{
+    clock::SharedClock,
+    exemplar::{self, Exemplar},
+    otlp_recorder::AbsoluteStrategy,
+};
 
-use crate::time::current_time;
+pub type MetricValues = HashMap<Key, MetricData>;
 
-pub type MetricValues = Vec<(Key, MetricData)>;
+/// Histogram label holding comma separated bucket bounds
+pub const BUCKETS_LABEL: &str = "buckets";
+
+/// Bucket bounds at the end of a histogram description as `[buckets=10,30]`, returns the
+/// description without them
+pub fn description_buckets(description: SharedString) -> (SharedString, Option<Vec<f64>>) {
+    let parsed = description.trim_end().strip_suffix(']').and_then(|rest| {
+        let start = rest.rfind('[')?;
+        let bounds = rest[start + 1..]
+            .strip_prefix(BUCKETS_LABEL)?
+            .trim_start()
+            .strip_prefix('=')?;
+        let bounds = bounds
+            .split(',')
+            .map(|v| v.trim().parse().ok())
+            .collect::<Option<Vec<f64>>>()?;
+        Some((rest[..start].trim_end().to_string(), bounds))
+    });
+    match parsed {
+        Some((description, bounds)) => (description.into(), Some(bounds)),
+        None => (description, None),
+    }
+}
 
+#[derive(Clone)]
 pub enum MetricType {
     Counter(Arc<CounterValue>),
     Gauge(Arc<GaugeValue>),
     Histogram(Arc<HistogramValue>),
+    DoubleCounter(Arc<DoubleCounterValue>),
+    Summary(Arc<SummaryValue>),
+    ExponentialHistogram(Arc<ExponentialHistogramValue>),
 }
 
 impl Display for MetricType {
@@ -22,6 +60,32 @@ impl Display for MetricType {
             MetricType::Counter(_metadata) => write!(f, "counter"),
             MetricType::Gauge(_) => write!(f, "gauge"),
             MetricType::Histogram(_) => write!(f, "histogram"),
+            MetricType::DoubleCounter(_) => write!(f, "double counter"),
+            MetricType::Summary(_) => write!(f, "summary"),
+            MetricType::ExponentialHistogram(_) => write!(f, "exponential histogram"),
+        }
+    }
+}
+
+/// Label value classified into an OTLP attribute type
+#[derive(Debug, PartialEq)]
+pub enum AttributeValue<'a> {
+    String(&'a str),
+    Int(i64),
+    Double(f64),
+    Bool(bool),
+}
+
+impl<'a> From<&'a str> for AttributeValue<'a> {
+    fn from(value: &'a str) -> Self {
+        if let Ok(v) = value.parse() {
+            Self::Int(v)
+        } else if let Some(v) = value.parse().ok().filter(|v: &f64| v.is_finite()) {
+            Self::Double(v)
+        } else if let Ok(v) = value.parse() {
+            Self::Bool(v)
+        } else {
+            Self::String(value)
         }
     }
 }
@@ -30,37 +94,170 @@ pub struct MetricDescription {
     pub key: KeyName,
     pub description: SharedString,
     pub unit: Option<Unit>,
+    /// Histogram bucket bounds given with the description
+    pub buckets: Option<Vec<f64>>,
 }
 
 pub struct MetricData {
-    pub start_time: u64,
+    pub start_time: AtomicU64,
+    /// Time the series was registered, keeps series never written alive for the TTL
+    pub registered: u64,
     pub description: SharedString,
     pub unit: Option<Unit>,
     pub metric_type: MetricType,
+    /// Latest trace id captured by [`crate::otlp_recorder::OtlpRecorder::trace_id_attribute`]
+    pub trace_id: Arc<Mutex<Option<String>>>,
+}
+
+/// Copy sharing the underlying values, only the start time is not shared
+impl Clone for MetricData {
+    fn clone(&self) -> Self {
+        Self {
+            start_time: AtomicU64::new(self.start_time()),
+            registered: self.registered,
+            description: self.description.clone(),
+            unit: self.unit,
+            metric_type: self.metric_type.clone(),
+            trace_id: self.trace_id.clone(),
+        }
+    }
 }
 
 impl MetricData {
-    pub fn basic(metric_type: MetricType) -> Self {
+    /// Series registered now, starting now
+    pub fn basic(metric_type: MetricType, now: u64) -> Self {
         Self {
             unit: None,
-            start_time: current_time(),
+            start_time: AtomicU64::new(now),
+            registered: now,
             description: SharedString::default(),
             metric_type,
+            trace_id: Default::default(),
         }
     }
 
     pub fn unit(&self) -> &str {
         self.unit.map(|u| u.as_canonical_label()).unwrap_or("1")
     }
+
+    pub fn start_time(&self) -> u64 {
+        self.start_time.load(Ordering::Relaxed)
+    }
+
+    /// Timestamp of an exported data point
+    ///
+    /// Values never written since registration or reset report their start time, so
+    /// `timeUnixNano` is never zero or before `startTimeUnixNano`.
+    pub fn point_time(&self, time: u64) -> u64 {
+        time.max(self.start_time())
+    }
+
+    /// Labels exported as attributes, the histogram buckets label is configuration only
+    pub fn labels<'a>(&self, key: &'a Key) -> impl Iterator<Item = &'a Label> {
+        let histogram = matches!(
+            self.metric_type,
+            MetricType::Histogram(_) | MetricType::Summary(_) | MetricType::ExponentialHistogram(_)
+        );
+        key.labels()
+            .filter(move |l| !histogram || l.key() != BUCKETS_LABEL)
+    }
+
+    /// Last update time of the underlying value
+    pub fn time(&self) -> u64 {
+        match &self.metric_type {
+            MetricType::Counter(v) => v.time(),
+            MetricType::Gauge(v) => v.time(),
+            MetricType::Histogram(v) => v.time(),
+            MetricType::DoubleCounter(v) => v.time(),
+            MetricType::Summary(v) => v.time(),
+            MetricType::ExponentialHistogram(v) => v.time(),
+        }
+    }
+
+    /// Copy for a delta export, values reset after the export are copied instead of shared so
+    /// the exact exported values can be subtracted by [`MetricData::reset_exported`]
+    pub fn frozen(&self) -> Self {
+        let mut data = self.clone();
+        data.metric_type = match &self.metric_type {
+            MetricType::Counter(v) => MetricType::Counter(Arc::new(v.frozen())),
+            MetricType::Histogram(v) => MetricType::Histogram(Arc::new(v.frozen())),
+            MetricType::DoubleCounter(v) => MetricType::DoubleCounter(Arc::new(v.frozen())),
+            MetricType::ExponentialHistogram(v) => {
+                MetricType::ExponentialHistogram(Arc::new(v.frozen()))
+            }
+            MetricType::Gauge(_) | MetricType::Summary(_) => return data,
+        };
+        data
+    }
+
+    /// Start a new delta period after exporting the [`MetricData::frozen`] copy
+    ///
+    /// Exported values are subtracted, values recorded during the export are kept for the
+    /// next period. Gauges keep their last value, summaries are cumulative only in OTLP and
+    /// are not reset.
+    pub fn reset_exported(&self, start_time: u64, exported: &MetricData) {
+        match (&self.metric_type, &exported.metric_type) {
+            (MetricType::Counter(v), MetricType::Counter(exported)) => v.subtract(exported.value()),
+            (MetricType::Histogram(v), MetricType::Histogram(exported)) => v.subtract(exported),
+            (MetricType::DoubleCounter(v), MetricType::DoubleCounter(exported)) => {
+                v.subtract(exported.value())
+            }
+            (MetricType::ExponentialHistogram(v), MetricType::ExponentialHistogram(exported)) => {
+                v.subtract(exported)
+            }
+            _ => return,
+        }
+        self.start_time.store(start_time, Ordering::Relaxed);
+    }
+
+    /// Start a new delta period, gauges keep their last value
+    pub fn reset(&self, start_time: u64) {
+        match &self.metric_type {
+            MetricType::Counter(v) => v.reset(),
+            MetricType::Gauge(_) => return,
+            MetricType::Histogram(v) => v.reset(),
+            MetricType::DoubleCounter(v) => v.reset(),
+            MetricType::Summary(v) => v.reset(),
+            MetricType::ExponentialHistogram(v) => v.reset(),
+        }
+        self.start_time.store(start_time, Ordering::Relaxed);
+    }
 }
 
 #[derive(Default)]
 pub struct CounterValue {
     pub value: AtomicU64,
     pub time: AtomicU64,
+    clock: SharedClock,
+    absolute_strategy: AbsoluteStrategy,
+    /// Single slot holding the latest traced increment, empty when exemplars are disabled
+    pub exemplars: Vec<Mutex<Option<Exemplar>>>,
 }
 
 impl CounterValue {
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_exemplars(mut self, enabled: bool) -> Self {
+        self.exemplars = if enabled {
+            vec![Mutex::new(None)]
+        } else {
+            vec![]
+        };
+        self
+    }
+
+    pub fn with_absolute_strategy(mut self, strategy: AbsoluteStrategy) -> Self {
+        self.absolute_strategy = strategy;
+        self
+    }
+
+    pub fn exemplars(&self) -> Vec<Exemplar> {
+        exemplar::collect(&self.exemplars)
+    }
+
     pub fn value(&self) -> u64 {
         self.value.load(Ordering::Relaxed)
     }
@@ -68,17 +265,59 @@ impl CounterValue {
     pub fn time(&self) -> u64 {
         self.time.load(Ordering::Relaxed)
     }
+
+    pub fn reset(&self) {
+        let _ = self.value.swap(0, Ordering::AcqRel);
+        exemplar::reset(&self.exemplars);
+    }
+
+    /// Copy holding the current value and exemplars
+    pub fn frozen(&self) -> Self {
+        Self {
+            value: AtomicU64::new(self.value()),
+            time: AtomicU64::new(self.time()),
+            clock: self.clock.clone(),
+            absolute_strategy: self.absolute_strategy,
+            exemplars: self
+                .exemplars
+                .iter()
+                .map(|slot| Mutex::new(*slot.lock().expect("exemplar lock")))
+                .collect(),
+        }
+    }
+
+    /// Remove an exported value keeping increments made since, saturates at zero when the
+    /// value was overwritten with a smaller absolute value
+    pub fn subtract(&self, exported: u64) {
+        let _ = self
+            .value
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |curr| {
+                Some(curr.saturating_sub(exported))
+            });
+        exemplar::reset(&self.exemplars);
+    }
 }
 
 impl CounterFn for CounterValue {
     fn increment(&self, value: u64) {
         let _ = self.value.fetch_add(value, Ordering::Release);
-        let _ = self.time.swap(current_time(), Ordering::AcqRel);
+        let _ = self.time.fetch_max(self.clock.now(), Ordering::AcqRel);
+        if let Some(slot) = self.exemplars.first() {
+            exemplar::capture(slot, value as f64, &self.clock);
+        }
     }
 
     fn absolute(&self, value: u64) {
-        let _ = self.value.fetch_max(value, Ordering::AcqRel);
-        let _ = self.time.swap(current_time(), Ordering::AcqRel);
+        match self.absolute_strategy {
+            AbsoluteStrategy::Max => {
+                let _ = self.value.fetch_max(value, Ordering::AcqRel);
+            }
+            AbsoluteStrategy::Overwrite => self.value.store(value, Ordering::Release),
+        }
+        let _ = self.time.fetch_max(self.clock.now(), Ordering::AcqRel);
+        if let Some(slot) = self.exemplars.first() {
+            exemplar::capture(slot, value as f64, &self.clock);
+        }
     }
 }
 
@@ -86,72 +325,215 @@ impl CounterFn for CounterValue {
 pub struct GaugeValue {
     pub value: AtomicU64,
     pub time: AtomicU64,
+    /// Odd while a writer updates value and time, readers retry when it changes
+    sequence: AtomicU64,
+    clock: SharedClock,
 }
 
 impl GaugeValue {
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
     pub fn value(&self) -> f64 {
-        f64::from_bits(self.value.load(Ordering::Relaxed))
+        self.snapshot().0
     }
 
     pub fn time(&self) -> u64 {
-        self.time.load(Ordering::Relaxed)
+        self.snapshot().1
     }
-}
 
-impl GaugeFn for GaugeValue {
-    fn increment(&self, value: f64) {
+    /// Value together with the time it was written
+    pub fn snapshot(&self) -> (f64, u64) {
         loop {
-            let result = self
-                .value
-                .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |curr| {
-                    let input = f64::from_bits(curr);
-                    let output = input + value;
-                    Some(output.to_bits())
-                });
-
-            if result.is_ok() {
-                break;
+            let sequence = self.sequence.load(Ordering::Acquire);
+            if sequence & 1 == 1 {
+                spin_loop();
+                continue;
+            }
+            let value = self.value.load(Ordering::Relaxed);
+            let time = self.time.load(Ordering::Relaxed);
+            fence(Ordering::Acquire);
+            if self.sequence.load(Ordering::Relaxed) == sequence {
+                return (f64::from_bits(value), time);
             }
         }
-        let _ = self.time.swap(current_time(), Ordering::AcqRel);
     }
 
-    fn decrement(&self, value: f64) {
+    fn update(&self, update: impl FnOnce(f64) -> f64) {
+        let mut sequence = self.sequence.load(Ordering::Relaxed);
         loop {
-            let result = self
-                .value
-                .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |curr| {
-                    let input = f64::from_bits(curr);
-                    let output = input - value;
-                    Some(output.to_bits())
-                });
-
-            if result.is_ok() {
-                break;
+            if sequence & 1 == 1 {
+                spin_loop();
+                sequence = self.sequence.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.sequence.compare_exchange_weak(
+                sequence,
+                sequence + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => sequence = current,
             }
         }
-        let _ = self.time.swap(current_time(), Ordering::AcqRel);
+        // the odd sequence must be visible before the data stores, like crossbeam's SeqLock
+        fence(Ordering::Release);
+        let value = update(f64::from_bits(self.value.load(Ordering::Relaxed)));
+        self.value.store(value.to_bits(), Ordering::Relaxed);
+        self.time.fetch_max(self.clock.now(), Ordering::Relaxed);
+        self.sequence.store(sequence + 2, Ordering::Release);
+    }
+}
+
+impl GaugeFn for GaugeValue {
+    fn increment(&self, value: f64) {
+        self.update(|current| current + value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.update(|current| current - value);
     }
 
     fn set(&self, value: f64) {
-        let _ = self.value.swap(value.to_bits(), Ordering::AcqRel);
-        let _ = self.time.swap(current_time(), Ordering::AcqRel);
+        self.update(|_| value);
     }
 }
 
+/// Monotonic counter with a floating point value
+///
+/// The `metrics` facade only increments counters by `u64`, double counters are recorded
+/// through the gauge api and ignore decrements.
 #[derive(Default)]
+pub struct DoubleCounterValue {
+    pub value: AtomicU64,
+    pub time: AtomicU64,
+    clock: SharedClock,
+    /// Up down counters accept decrements and negative increments, `set` overwrites the value
+    up_down: bool,
+}
+
+impl DoubleCounterValue {
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_up_down(mut self, up_down: bool) -> Self {
+        self.up_down = up_down;
+        self
+    }
+
+    pub fn value(&self) -> f64 {
+        f64::from_bits(self.value.load(Ordering::Relaxed))
+    }
+
+    pub fn time(&self) -> u64 {
+        self.time.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        let _ = self.value.swap(0f64.to_bits(), Ordering::AcqRel);
+    }
+
+    /// Copy holding the current value
+    pub fn frozen(&self) -> Self {
+        Self {
+            value: AtomicU64::new(self.value.load(Ordering::Relaxed)),
+            time: AtomicU64::new(self.time()),
+            clock: self.clock.clone(),
+            up_down: self.up_down,
+        }
+    }
+
+    /// Remove an exported value keeping increments made since
+    pub fn subtract(&self, exported: f64) {
+        let _ = self
+            .value
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |curr| {
+                Some((f64::from_bits(curr) - exported).to_bits())
+            });
+    }
+}
+
+impl GaugeFn for DoubleCounterValue {
+    fn increment(&self, value: f64) {
+        if value.is_nan() || (value < 0.0 && !self.up_down) {
+            return;
+        }
+        let _ = self
+            .value
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |curr| {
+                Some((f64::from_bits(curr) + value).to_bits())
+            });
+        let _ = self.time.fetch_max(self.clock.now(), Ordering::AcqRel);
+    }
+
+    fn decrement(&self, value: f64) {
+        if self.up_down {
+            self.increment(-value);
+        }
+    }
+
+    fn set(&self, value: f64) {
+        let _ = self
+            .value
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |curr| {
+                (self.up_down || value > f64::from_bits(curr)).then_some(value.to_bits())
+            });
+        let _ = self.time.fetch_max(self.clock.now(), Ordering::AcqRel);
+    }
+}
+
 pub struct HistogramValue {
     pub sum: AtomicU64,
     pub count: AtomicU64,
     pub time: AtomicU64,
+    pub min: AtomicU64,
+    pub max: AtomicU64,
     pub explicit_bounds: Vec<f64>,
     pub bucket_count: Vec<AtomicU64>,
+    clock: SharedClock,
+    /// Latest traced observation per bucket, empty when exemplars are disabled
+    pub exemplars: Vec<Mutex<Option<Exemplar>>>,
+}
+
+impl Default for HistogramValue {
+    fn default() -> Self {
+        Self {
+            sum: Default::default(),
+            count: Default::default(),
+            time: Default::default(),
+            min: AtomicU64::new(f64::INFINITY.to_bits()),
+            max: AtomicU64::new(f64::NEG_INFINITY.to_bits()),
+            explicit_bounds: Default::default(),
+            bucket_count: Default::default(),
+            clock: Default::default(),
+            exemplars: Default::default(),
+        }
+    }
 }
 
 impl HistogramValue {
-    pub fn from_bounds(bounds: Vec<f64>) -> Self {
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Histogram with the given bucket bounds, unsorted bounds are sorted
+    ///
+    /// A `+Inf` bound is dropped as the last bucket always counts values above the last bound.
+    /// Bounds containing duplicates or other non finite values are ignored and no buckets are
+    /// used.
+    pub fn from_bounds(mut bounds: Vec<f64>) -> Self {
         let mut value = Self::default();
-        if !bounds.is_empty() {
+        bounds.retain(|b| *b != f64::INFINITY);
+        bounds.sort_by(f64::total_cmp);
+        if bounds.iter().any(|b| !b.is_finite()) || bounds.windows(2).any(|w| w[0] >= w[1]) {
+            error!("Invalid histogram bounds {bounds:?}, bounds must be finite and unique");
+        } else if !bounds.is_empty() {
             value.explicit_bounds = bounds;
             value.bucket_count = value
                 .explicit_bounds
@@ -163,6 +545,21 @@ impl HistogramValue {
         value
     }
 
+    pub fn with_exemplars(mut self, enabled: bool) -> Self {
+        self.exemplars = if enabled {
+            (0..self.bucket_count.len().max(1))
+                .map(|_| Mutex::new(None))
+                .collect()
+        } else {
+            vec![]
+        };
+        self
+    }
+
+    pub fn exemplars(&self) -> Vec<Exemplar> {
+        exemplar::collect(&self.exemplars)
+    }
+
     pub fn sum(&self) -> f64 {
         f64::from_bits(self.sum.load(Ordering::Relaxed))
     }
@@ -175,6 +572,16 @@ impl HistogramValue {
         self.time.load(Ordering::Relaxed)
     }
 
+    /// Smallest recorded value, `None` when nothing was recorded
+    pub fn min(&self) -> Option<f64> {
+        (self.count() > 0).then(|| f64::from_bits(self.min.load(Ordering::Relaxed)))
+    }
+
+    /// Largest recorded value, `None` when nothing was recorded
+    pub fn max(&self) -> Option<f64> {
+        (self.count() > 0).then(|| f64::from_bits(self.max.load(Ordering::Relaxed)))
+    }
+
     pub fn bucket_count(&self) -> Vec<u64> {
         self.bucket_count
             .iter()
@@ -182,9 +589,105 @@ impl HistogramValue {
             .collect()
     }
 
+    /// Count and per bucket counts read together for export
+    ///
+    /// With buckets the count is the sum of the bucket counts, so concurrent records never
+    /// make the exported count disagree with the buckets.
+    pub fn counts(&self) -> (u64, Vec<u64>) {
+        let buckets = self.bucket_count();
+        let count = if buckets.is_empty() {
+            self.count()
+        } else {
+            buckets.iter().sum()
+        };
+        (count, buckets)
+    }
+
+    /// Counts of values less than or equal to each bound as used by Prometheus, OTLP uses
+    /// the per bucket counts of [`HistogramValue::bucket_count`]
+    pub fn cumulative_bucket_counts(&self) -> Vec<u64> {
+        self.bucket_count()
+            .into_iter()
+            .scan(0, |total, count| {
+                *total += count;
+                Some(*total)
+            })
+            .collect()
+    }
+
     pub fn explicit_bounds(&self) -> &[f64] {
         &self.explicit_bounds
     }
+
+    pub fn reset(&self) {
+        let _ = self.sum.swap(0f64.to_bits(), Ordering::AcqRel);
+        let _ = self.count.swap(0, Ordering::AcqRel);
+        let _ = self.min.swap(f64::INFINITY.to_bits(), Ordering::AcqRel);
+        let _ = self.max.swap(f64::NEG_INFINITY.to_bits(), Ordering::AcqRel);
+        for bucket in &self.bucket_count {
+            let _ = bucket.swap(0, Ordering::AcqRel);
+        }
+        exemplar::reset(&self.exemplars);
+    }
+
+    /// Copy holding the current values and exemplars
+    pub fn frozen(&self) -> Self {
+        Self {
+            sum: AtomicU64::new(self.sum.load(Ordering::Relaxed)),
+            count: AtomicU64::new(self.count()),
+            time: AtomicU64::new(self.time()),
+            min: AtomicU64::new(self.min.load(Ordering::Relaxed)),
+            max: AtomicU64::new(self.max.load(Ordering::Relaxed)),
+            explicit_bounds: self.explicit_bounds.clone(),
+            bucket_count: self
+                .bucket_count()
+                .into_iter()
+                .map(AtomicU64::new)
+                .collect(),
+            clock: self.clock.clone(),
+            exemplars: self
+                .exemplars
+                .iter()
+                .map(|slot| Mutex::new(*slot.lock().expect("exemplar lock")))
+                .collect(),
+        }
+    }
+
+    /// Remove exported values keeping values recorded since
+    ///
+    /// Min and max are reset when nothing was recorded since, otherwise they keep covering
+    /// the exported values as well.
+    pub fn subtract(&self, exported: &HistogramValue) {
+        let saturating_sub = |value: &AtomicU64, exported: u64| {
+            let _ = value.fetch_update(Ordering::AcqRel, Ordering::Acquire, |curr| {
+                Some(curr.saturating_sub(exported))
+            });
+        };
+        for (bucket, exported) in self.bucket_count.iter().zip(exported.bucket_count()) {
+            saturating_sub(bucket, exported);
+        }
+        let _ = self
+            .sum
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |curr| {
+                Some((f64::from_bits(curr) - exported.sum()).to_bits())
+            });
+        saturating_sub(&self.count, exported.count());
+        if self.count() == 0 {
+            let _ = self.min.compare_exchange(
+                exported.min.load(Ordering::Relaxed),
+                f64::INFINITY.to_bits(),
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            );
+            let _ = self.max.compare_exchange(
+                exported.max.load(Ordering::Relaxed),
+                f64::NEG_INFINITY.to_bits(),
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            );
+        }
+        exemplar::reset(&self.exemplars);
+    }
 }
 
 impl HistogramFn for HistogramValue {
@@ -203,43 +706,469 @@ impl HistogramFn for HistogramValue {
             }
         }
 
-        if !self.explicit_bounds.is_empty() {
-            let mut bounds = self.explicit_bounds.iter();
-            let mut buckets = self.bucket_count.iter();
-            let mut prev_bound = bounds.next().expect("At least one bound");
-            let bucket = buckets.next().expect("At least one bucket");
-            if &value <= prev_bound {
-                let _ = bucket.fetch_add(1, Ordering::Release);
-            }
-            loop {
-                let bound = bounds.next();
-                let bucket = buckets.next();
-                if let Some(b) = bound {
-                    if &value > prev_bound && &value <= b {
-                        let _ = bucket
-                            .expect("At least one bound")
-                            .fetch_add(1, Ordering::Release);
-                    }
-                    prev_bound = b;
-                } else {
-                    if &value > prev_bound {
-                        let _ = bucket
-                            .expect("At least one bound")
-                            .fetch_add(1, Ordering::Release);
-                    }
-                    break;
-                }
+        let _ = self
+            .min
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |curr| {
+                (value < f64::from_bits(curr)).then_some(value.to_bits())
+            });
+        let _ = self
+            .max
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |curr| {
+                (value > f64::from_bits(curr)).then_some(value.to_bits())
+            });
+
+        // bounds are sorted and unique, the first bound not below the value is its upper
+        // bound, values above every bound land in the last bucket
+        let bucket = self.explicit_bounds.partition_point(|b| *b < value);
+        if !value.is_nan() {
+            if let Some(count) = self.bucket_count.get(bucket) {
+                let _ = count.fetch_add(1, Ordering::Release);
             }
         }
 
         let _ = self.count.fetch_add(1, Ordering::Release);
-        let _ = self.time.swap(current_time(), Ordering::AcqRel);
+        let _ = self.time.fetch_max(self.clock.now(), Ordering::AcqRel);
+
+        if !self.exemplars.is_empty() {
+            exemplar::capture(
+                &self.exemplars[bucket.min(self.exemplars.len() - 1)],
+                value,
+                &self.clock,
+            );
+        }
+    }
+}
+
+/// Relative error of quantiles estimated by summaries
+const SUMMARY_ACCURACY: f64 = 0.01;
+
+/// Streaming quantile estimator counting values in logarithmically sized buckets
+///
+/// Estimated quantiles are within [`SUMMARY_ACCURACY`] relative error of the recorded values
+/// (DDSketch), memory grows with the logarithm of the value range only.
+struct Sketch {
+    gamma_ln: f64,
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+    zero: u64,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for Sketch {
+    fn default() -> Self {
+        let gamma = (1.0 + SUMMARY_ACCURACY) / (1.0 - SUMMARY_ACCURACY);
+        Self {
+            gamma_ln: gamma.ln(),
+            positive: Default::default(),
+            negative: Default::default(),
+            zero: 0,
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Sketch {
+    fn insert(&mut self, value: f64) {
+        if value > f64::MIN_POSITIVE {
+            *self.positive.entry(self.index(value)).or_default() += 1;
+        } else if value < -f64::MIN_POSITIVE {
+            *self.negative.entry(self.index(-value)).or_default() += 1;
+        } else {
+            self.zero += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn index(&self, value: f64) -> i32 {
+        (value.ln() / self.gamma_ln).ceil() as i32
+    }
+
+    /// Midpoint of the bucket with the given index
+    fn bucket_value(&self, index: i32) -> f64 {
+        let gamma = self.gamma_ln.exp();
+        2.0 * (self.gamma_ln * index as f64).exp() / (gamma + 1.0)
+    }
+
+    fn quantile(&self, quantile: f64) -> f64 {
+        let rank = (quantile * (self.count - 1) as f64).round() as u64;
+        let mut seen = 0;
+        // negative values in ascending order have descending bucket indexes
+        for (index, count) in self.negative.iter().rev() {
+            seen += count;
+            if seen > rank {
+                return self.clamp(-self.bucket_value(*index));
+            }
+        }
+        seen += self.zero;
+        if seen > rank {
+            return 0.0;
+        }
+        for (index, count) in &self.positive {
+            seen += count;
+            if seen > rank {
+                return self.clamp(self.bucket_value(*index));
+            }
+        }
+        self.max
+    }
+
+    fn clamp(&self, value: f64) -> f64 {
+        value.clamp(self.min, self.max)
+    }
+}
+
+/// Histogram exported as a summary with estimated quantiles
+pub struct SummaryValue {
+    pub time: AtomicU64,
+    quantiles: Vec<f64>,
+    sketch: Mutex<Sketch>,
+    clock: SharedClock,
+}
+
+impl SummaryValue {
+    /// Summary reporting the given quantiles, quantiles outside of 0..=1 are ignored
+    pub fn new(mut quantiles: Vec<f64>) -> Self {
+        quantiles.retain(|q| {
+            let valid = (0.0..=1.0).contains(q);
+            if !valid {
+                error!("Invalid summary quantile {q}, quantiles must be between 0 and 1");
+            }
+            valid
+        });
+        quantiles.sort_by(f64::total_cmp);
+        quantiles.dedup();
+        Self {
+            time: Default::default(),
+            quantiles,
+            sketch: Default::default(),
+            clock: Default::default(),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn count(&self) -> u64 {
+        self.sketch.lock().expect("sketch lock").count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sketch.lock().expect("sketch lock").sum
+    }
+
+    pub fn time(&self) -> u64 {
+        self.time.load(Ordering::Relaxed)
+    }
+
+    /// Configured quantiles with their estimated values, empty when nothing was recorded
+    pub fn quantile_values(&self) -> Vec<(f64, f64)> {
+        let sketch = self.sketch.lock().expect("sketch lock");
+        if sketch.count == 0 {
+            return Vec::new();
+        }
+        self.quantiles
+            .iter()
+            .map(|q| match *q {
+                0.0 => (*q, sketch.min),
+                1.0 => (*q, sketch.max),
+                _ => (*q, sketch.quantile(*q)),
+            })
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        *self.sketch.lock().expect("sketch lock") = Sketch::default();
+    }
+}
+
+impl HistogramFn for SummaryValue {
+    fn record(&self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.sketch.lock().expect("sketch lock").insert(value);
+        let _ = self.time.fetch_max(self.clock.now(), Ordering::AcqRel);
+    }
+}
+
+/// Initial scale of exponential histograms, the highest scale defined by OTLP
+const EXPONENTIAL_MAX_SCALE: i32 = 20;
+/// Maximum number of positive or negative buckets before the scale is reduced
+const EXPONENTIAL_MAX_BUCKETS: usize = 160;
+
+/// Bucket index of a positive value, bucket `i` holds values in `(base^i, base^(i + 1)]`
+/// where `base = 2^(2^-scale)`
+pub fn exponential_index(value: f64, scale: i32) -> i32 {
+    let bits = value.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32 - 1023;
+    let power_of_two = bits & ((1 << 52) - 1) == 0 && exponent > -1023;
+    if scale <= 0 {
+        // powers of two are the upper bound of the bucket below
+        let exponent = if power_of_two {
+            exponent - 1
+        } else {
+            value.log2().floor() as i32
+        };
+        return exponent >> -scale;
+    }
+    if power_of_two {
+        return (exponent << scale) - 1;
+    }
+    (value.log2() * f64::from(1 << scale)).ceil() as i32 - 1
+}
+
+/// Consecutive bucket counts starting at `offset`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExponentialBuckets {
+    pub offset: i32,
+    pub counts: Vec<u64>,
+}
+
+impl ExponentialBuckets {
+    fn range_with(&self, index: i32) -> (i32, i32) {
+        if self.counts.is_empty() {
+            (index, index)
+        } else {
+            (
+                self.offset.min(index),
+                (self.offset + self.counts.len() as i32 - 1).max(index),
+            )
+        }
+    }
+
+    fn add(&mut self, index: i32, count: u64) {
+        if self.counts.is_empty() {
+            self.offset = index;
+            self.counts.push(0);
+        } else if index < self.offset {
+            let missing = (self.offset - index) as usize;
+            self.counts.splice(0..0, core::iter::repeat_n(0, missing));
+            self.offset = index;
+        } else if index >= self.offset + self.counts.len() as i32 {
+            self.counts.resize((index - self.offset) as usize + 1, 0);
+        }
+        self.counts[(index - self.offset) as usize] += count;
+    }
+
+    /// Remove the counts of buckets at the same scale, empty buckets at both ends are dropped
+    fn subtract(&mut self, other: &ExponentialBuckets) {
+        for (i, count) in other.counts.iter().enumerate() {
+            let index = other.offset + i as i32 - self.offset;
+            if let Some(current) = usize::try_from(index)
+                .ok()
+                .and_then(|index| self.counts.get_mut(index))
+            {
+                *current = current.saturating_sub(*count);
+            }
+        }
+        let leading = self.counts.iter().take_while(|c| **c == 0).count();
+        self.counts.drain(..leading);
+        self.offset += leading as i32;
+        while self.counts.last() == Some(&0) {
+            self.counts.pop();
+        }
+    }
+
+    /// Merge buckets for a scale lower by `change`
+    fn downscale(&mut self, change: i32) {
+        if change == 0 || self.counts.is_empty() {
+            return;
+        }
+        let mut merged = ExponentialBuckets::default();
+        for (i, count) in self.counts.iter().enumerate() {
+            merged.add((self.offset + i as i32) >> change, *count);
+        }
+        *self = merged;
+    }
+}
+
+#[derive(Clone)]
+struct ExponentialState {
+    scale: i32,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    zero_count: u64,
+    positive: ExponentialBuckets,
+    negative: ExponentialBuckets,
+}
+
+impl Default for ExponentialState {
+    fn default() -> Self {
+        Self {
+            scale: EXPONENTIAL_MAX_SCALE,
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            zero_count: 0,
+            positive: Default::default(),
+            negative: Default::default(),
+        }
+    }
+}
+
+impl ExponentialState {
+    /// Remove the counts of an earlier copy, the scale only decreases so the buckets of the
+    /// copy are merged to the current scale first
+    fn subtract(&mut self, exported: &ExponentialState) {
+        if exported.count >= self.count {
+            *self = Self::default();
+            return;
+        }
+        let change = exported.scale - self.scale;
+        for (buckets, exported) in [
+            (&mut self.positive, &exported.positive),
+            (&mut self.negative, &exported.negative),
+        ] {
+            let mut exported = exported.clone();
+            exported.downscale(change);
+            buckets.subtract(&exported);
+        }
+        self.count -= exported.count;
+        self.sum -= exported.sum;
+        self.zero_count = self.zero_count.saturating_sub(exported.zero_count);
+    }
+
+    fn record(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        if value == 0.0 {
+            self.zero_count += 1;
+            return;
+        }
+        let index = exponential_index(value.abs(), self.scale);
+        let buckets = if value > 0.0 {
+            &self.positive
+        } else {
+            &self.negative
+        };
+        let (low, high) = buckets.range_with(index);
+        let mut change = 0;
+        while ((high >> change) - (low >> change)) as usize >= EXPONENTIAL_MAX_BUCKETS {
+            change += 1;
+        }
+        if change > 0 {
+            self.scale -= change;
+            self.positive.downscale(change);
+            self.negative.downscale(change);
+        }
+        let index = index >> change;
+        if value > 0.0 {
+            self.positive.add(index, 1);
+        } else {
+            self.negative.add(index, 1);
+        }
+    }
+}
+
+/// Point in time copy of an exponential histogram
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExponentialSnapshot {
+    pub scale: i32,
+    pub count: u64,
+    pub sum: f64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub zero_count: u64,
+    pub positive: ExponentialBuckets,
+    pub negative: ExponentialBuckets,
+}
+
+/// Histogram with base 2 exponential buckets adapting the scale to the recorded values
+#[derive(Default)]
+pub struct ExponentialHistogramValue {
+    pub time: AtomicU64,
+    state: Mutex<ExponentialState>,
+    clock: SharedClock,
+}
+
+impl ExponentialHistogramValue {
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn time(&self) -> u64 {
+        self.time.load(Ordering::Relaxed)
+    }
+
+    pub fn snapshot(&self) -> ExponentialSnapshot {
+        let state = self.state.lock().expect("exponential histogram lock");
+        ExponentialSnapshot {
+            scale: state.scale,
+            count: state.count,
+            sum: state.sum,
+            min: (state.count > 0).then_some(state.min),
+            max: (state.count > 0).then_some(state.max),
+            zero_count: state.zero_count,
+            positive: state.positive.clone(),
+            negative: state.negative.clone(),
+        }
+    }
+
+    pub fn reset(&self) {
+        *self.state.lock().expect("exponential histogram lock") = ExponentialState::default();
+    }
+
+    /// Copy holding the current state
+    pub fn frozen(&self) -> Self {
+        Self {
+            time: AtomicU64::new(self.time()),
+            state: Mutex::new(
+                self.state
+                    .lock()
+                    .expect("exponential histogram lock")
+                    .clone(),
+            ),
+            clock: self.clock.clone(),
+        }
+    }
+
+    /// Remove exported values keeping values recorded since
+    pub fn subtract(&self, exported: &ExponentialHistogramValue) {
+        let exported = exported.state.lock().expect("exponential histogram lock");
+        self.state
+            .lock()
+            .expect("exponential histogram lock")
+            .subtract(&exported);
+    }
+}
+
+impl HistogramFn for ExponentialHistogramValue {
+    fn record(&self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        self.state
+            .lock()
+            .expect("exponential histogram lock")
+            .record(value);
+        let _ = self.time.fetch_max(self.clock.now(), Ordering::AcqRel);
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use core::sync::atomic::AtomicBool;
+    use std::thread::spawn;
+
     use super::*;
+    use crate::clock::Clock;
 
     #[test]
     fn test_histogram_bounds() {
@@ -261,6 +1190,313 @@ mod tests {
         assert_eq!(histogram.sum(), 1104.0);
     }
 
+    #[test]
+    fn test_histogram_bucket_search_matches_linear_scan() {
+        let bounds: Vec<f64> = (1..=100).map(|b| f64::from(b) / 2.0).collect();
+        let histogram = HistogramValue::from_bounds(bounds.clone());
+        let mut expected = vec![0; bounds.len() + 1];
+        for i in -10..250 {
+            let value = f64::from(i) / 4.0;
+            histogram.record(value);
+            let bucket = bounds
+                .iter()
+                .position(|b| value <= *b)
+                .unwrap_or(bounds.len());
+            expected[bucket] += 1;
+        }
+        assert_eq!(histogram.bucket_count(), expected);
+    }
+
+    #[test]
+    fn test_description_buckets() {
+        for (description, expected_description, expected_buckets) in [
+            (
+                "Request time [buckets=10,30]",
+                "Request time",
+                Some(vec![10.0, 30.0]),
+            ),
+            ("[buckets = 0.5, 1 ] ", "", Some(vec![0.5, 1.0])),
+            ("Request time", "Request time", None),
+            ("Request time [ms]", "Request time [ms]", None),
+            (
+                "Request time [buckets=a,1]",
+                "Request time [buckets=a,1]",
+                None,
+            ),
+        ] {
+            let (description, buckets) = description_buckets(description.into());
+            assert_eq!(&*description, expected_description);
+            assert_eq!(buckets, expected_buckets);
+        }
+    }
+
+    #[test]
+    fn test_cumulative_bucket_counts() {
+        let histogram = HistogramValue::from_bounds(vec![10.0, 30.0]);
+        assert_eq!(histogram.cumulative_bucket_counts(), vec![0, 0, 0]);
+        histogram.record(5.0);
+        histogram.record(20.0);
+
+        assert_eq!(histogram.bucket_count(), vec![1, 1, 0]);
+        assert_eq!(histogram.cumulative_bucket_counts(), vec![1, 2, 2]);
+    }
+
+    #[test]
+    fn test_histogram_values_on_bounds() {
+        let histogram = HistogramValue::from_bounds(vec![10.0, 20.0, 30.0]);
+        for value in [10.0, 20.0, 30.0, 30.5, -1.0] {
+            histogram.record(value);
+        }
+        assert_eq!(histogram.bucket_count(), vec![2, 1, 1, 1]);
+
+        let histogram = HistogramValue::from_bounds(vec![10.0]);
+        histogram.record(10.0);
+        histogram.record(f64::from_bits(10f64.to_bits() + 1));
+        assert_eq!(histogram.bucket_count(), vec![1, 1]);
+
+        let histogram = HistogramValue::from_bounds(vec![10.0, 20.0, 20.0]);
+        histogram.record(20.0);
+        assert!(histogram.bucket_count().is_empty());
+        assert_eq!(histogram.count(), 1);
+    }
+
+    #[test]
+    fn test_histogram_infinite_bounds() {
+        let histogram = HistogramValue::from_bounds(vec![10.0, 30.0, f64::INFINITY]);
+        assert_eq!(histogram.explicit_bounds(), &[10.0, 30.0]);
+        histogram.record(5.0);
+        histogram.record(30.0);
+        histogram.record(1000.0);
+        assert_eq!(histogram.bucket_count(), vec![1, 1, 1]);
+
+        let histogram = HistogramValue::from_bounds(vec![f64::INFINITY, 10.0]);
+        assert_eq!(histogram.explicit_bounds(), &[10.0]);
+        assert_eq!(histogram.bucket_count(), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_histogram_counts_concurrent_records() {
+        let histogram = Arc::new(HistogramValue::from_bounds(vec![10.0, 30.0]));
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let histogram = histogram.clone();
+            let done = done.clone();
+            spawn(move || {
+                while !done.load(Ordering::Acquire) {
+                    let (count, buckets) = histogram.counts();
+                    assert_eq!(count, buckets.iter().sum::<u64>());
+                }
+            })
+        };
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let histogram = histogram.clone();
+                spawn(move || {
+                    for j in 0..1000 {
+                        histogram.record(f64::from(i * 10 + j % 40));
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::Release);
+        reader.join().unwrap();
+
+        let (count, buckets) = histogram.counts();
+        assert_eq!(count, 8000);
+        assert_eq!(histogram.count(), 8000);
+        assert_eq!(buckets.iter().sum::<u64>(), count);
+    }
+
+    #[test]
+    fn test_counter_absolute_strategy() {
+        let counter = CounterValue::default();
+        counter.absolute(10);
+        counter.absolute(5);
+        assert_eq!(counter.value(), 10);
+        counter.increment(1);
+        counter.absolute(20);
+        assert_eq!(counter.value(), 20);
+
+        let counter = CounterValue::default().with_absolute_strategy(AbsoluteStrategy::Overwrite);
+        counter.absolute(10);
+        counter.absolute(5);
+        assert_eq!(counter.value(), 5);
+        counter.increment(1);
+        assert_eq!(counter.value(), 6);
+        counter.absolute(20);
+        assert_eq!(counter.value(), 20);
+    }
+
+    #[test]
+    fn test_summary_quantiles() {
+        let summary = SummaryValue::new(vec![0.99, 0.5, 0.9, 0.0, 1.0, 2.0]);
+        assert!(summary.quantile_values().is_empty());
+        for value in (1..=1000).rev() {
+            summary.record(value as f64);
+        }
+
+        assert_eq!(summary.count(), 1000);
+        assert_eq!(summary.sum(), 500500.0);
+        let values = summary.quantile_values();
+        let quantiles: Vec<f64> = values.iter().map(|(q, _)| *q).collect();
+        assert_eq!(quantiles, [0.0, 0.5, 0.9, 0.99, 1.0]);
+        for ((_, value), expected) in values.iter().zip([1.0, 500.0, 900.0, 990.0, 1000.0]) {
+            assert!(
+                (value - expected).abs() <= expected * SUMMARY_ACCURACY,
+                "{value} {expected}"
+            );
+        }
+
+        summary.record(-5.0);
+        summary.record(0.0);
+        assert_eq!(summary.quantile_values()[0], (0.0, -5.0));
+        summary.reset();
+        assert_eq!(summary.count(), 0);
+    }
+
+    #[test]
+    fn test_exponential_index() {
+        for (value, scale, index) in [
+            (1.0, 0, -1),
+            (1.5, 0, 0),
+            (2.0, 0, 0),
+            (3.0, 0, 1),
+            (4.0, 0, 1),
+            (0.5, 0, -2),
+            (1.5, 1, 1),
+            (2.0, 1, 1),
+            (2.5, 1, 2),
+            (3.0, -1, 0),
+            (4.0, -1, 0),
+            (5.0, -1, 1),
+            (1024.0, 20, (10 << 20) - 1),
+        ] {
+            assert_eq!(exponential_index(value, scale), index, "{value} {scale}");
+        }
+    }
+
+    #[test]
+    fn test_exponential_histogram() {
+        let histogram = ExponentialHistogramValue::default();
+        histogram.record(0.0);
+        histogram.record(-3.0);
+        histogram.record(1.0);
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.scale, EXPONENTIAL_MAX_SCALE);
+        assert_eq!(snapshot.zero_count, 1);
+        assert_eq!(snapshot.positive.counts, [1]);
+        assert_eq!(snapshot.positive.offset, -1);
+
+        for value in 1..=1000 {
+            histogram.record(value as f64);
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 1003);
+        assert_eq!(snapshot.sum, 500498.0);
+        assert_eq!((snapshot.min, snapshot.max), (Some(-3.0), Some(1000.0)));
+        assert!(snapshot.positive.counts.len() <= EXPONENTIAL_MAX_BUCKETS);
+        assert_eq!(snapshot.scale, 3);
+        assert_eq!(snapshot.positive.counts.iter().sum::<u64>(), 1001);
+        assert_eq!(snapshot.negative.offset, exponential_index(3.0, 3));
+        assert_eq!(snapshot.negative.counts, [1]);
+        // 1000 is in the last bucket
+        assert_eq!(
+            snapshot.positive.offset + snapshot.positive.counts.len() as i32 - 1,
+            exponential_index(1000.0, 3)
+        );
+
+        histogram.reset();
+        assert_eq!(histogram.snapshot().count, 0);
+    }
+
+    #[test]
+    fn test_reset_exported_keeps_values_recorded_during_export() {
+        let histogram = Arc::new(HistogramValue::from_bounds(vec![10.0, 30.0]));
+        let data = MetricData::basic(MetricType::Histogram(histogram.clone()), 0);
+        histogram.record(5.0);
+        histogram.record(20.0);
+        let exported = data.frozen();
+        histogram.record(40.0);
+        data.reset_exported(1, &exported);
+        assert_eq!(histogram.counts(), (1, vec![0, 0, 1]));
+        assert_eq!(histogram.sum(), 40.0);
+        assert_eq!(data.start_time(), 1);
+
+        let double_counter = Arc::new(DoubleCounterValue::default());
+        let data = MetricData::basic(MetricType::DoubleCounter(double_counter.clone()), 0);
+        double_counter.increment(1.5);
+        let exported = data.frozen();
+        double_counter.increment(2.0);
+        data.reset_exported(1, &exported);
+        assert_eq!(double_counter.value(), 2.0);
+
+        let exponential = Arc::new(ExponentialHistogramValue::default());
+        let data = MetricData::basic(MetricType::ExponentialHistogram(exponential.clone()), 0);
+        exponential.record(1.0);
+        exponential.record(0.0);
+        let exported = data.frozen();
+        // lowers the scale of the buckets after the copy was taken
+        for value in 1..=1000 {
+            exponential.record(value as f64);
+        }
+        data.reset_exported(1, &exported);
+        let snapshot = exponential.snapshot();
+        assert_eq!(snapshot.count, 1000);
+        assert_eq!(snapshot.zero_count, 0);
+        assert_eq!(snapshot.positive.counts.iter().sum::<u64>(), 1000);
+        assert_eq!(snapshot.positive.counts.first(), Some(&1));
+
+        let exported = data.frozen();
+        data.reset_exported(2, &exported);
+        assert_eq!(exponential.snapshot().count, 0);
+        assert_eq!(exponential.snapshot().scale, EXPONENTIAL_MAX_SCALE);
+    }
+
+    #[test]
+    fn test_histogram_invalid_bounds() {
+        let histogram = HistogramValue::from_bounds(vec![30.0, 10.0]);
+        assert_eq!(histogram.explicit_bounds(), &[10.0, 30.0]);
+        histogram.record(20.0);
+        assert_eq!(histogram.bucket_count(), vec![0, 1, 0]);
+
+        let histogram = HistogramValue::from_bounds(vec![10.0, 10.0, 30.0]);
+        assert!(histogram.explicit_bounds().is_empty());
+        assert!(histogram.bucket_count().is_empty());
+
+        let histogram = HistogramValue::from_bounds(vec![10.0, f64::NAN]);
+        assert!(histogram.explicit_bounds().is_empty());
+
+        let histogram = HistogramValue::from_bounds(vec![10.0, f64::NEG_INFINITY]);
+        assert!(histogram.explicit_bounds().is_empty());
+    }
+
+    #[test]
+    fn test_histogram_min_max() {
+        let histogram = HistogramValue::default();
+        assert_eq!(histogram.min(), None);
+        assert_eq!(histogram.max(), None);
+        histogram.record(5.0);
+        histogram.record(1.0);
+        histogram.record(9.0);
+        assert_eq!(histogram.min(), Some(1.0));
+        assert_eq!(histogram.max(), Some(9.0));
+    }
+
+    #[test]
+    fn test_attribute_value_types() {
+        assert_eq!(AttributeValue::from("200"), AttributeValue::Int(200));
+        assert_eq!(AttributeValue::from("-3"), AttributeValue::Int(-3));
+        assert_eq!(AttributeValue::from("0.5"), AttributeValue::Double(0.5));
+        assert_eq!(AttributeValue::from("true"), AttributeValue::Bool(true));
+        assert_eq!(AttributeValue::from("false"), AttributeValue::Bool(false));
+        assert_eq!(AttributeValue::from("NaN"), AttributeValue::String("NaN"));
+        assert_eq!(AttributeValue::from("inf"), AttributeValue::String("inf"));
+        assert_eq!(AttributeValue::from("/a"), AttributeValue::String("/a"));
+    }
+
     #[test]
     fn test_gauge() {
         let value = GaugeValue::default();
@@ -270,6 +1506,63 @@ mod tests {
         assert_eq!(value.value(), 10.0);
     }
 
+    #[test]
+    fn test_double_counter() {
+        let counter = DoubleCounterValue::default();
+        counter.increment(0.5);
+        counter.increment(1.25);
+        assert_eq!(counter.value(), 1.75);
+        counter.decrement(1.0);
+        counter.increment(-1.0);
+        assert_eq!(counter.value(), 1.75);
+        counter.set(1.0);
+        assert_eq!(counter.value(), 1.75);
+        counter.set(3.5);
+        assert_eq!(counter.value(), 3.5);
+    }
+
+    #[test]
+    fn test_gauge_snapshot_is_consistent() {
+        /// Ticks once per gauge update as updates read the clock while holding the sequence
+        struct TickClock(AtomicU64);
+
+        impl Clock for TickClock {
+            fn now_unix_nanos(&self) -> u64 {
+                self.0.fetch_add(1, Ordering::Relaxed) + 1
+            }
+        }
+
+        let clock = SharedClock::new(Arc::new(TickClock(AtomicU64::new(0))));
+        let gauge = Arc::new(GaugeValue::default().with_clock(clock));
+        let done = Arc::new(AtomicBool::new(false));
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let gauge = gauge.clone();
+                spawn(move || {
+                    for _ in 1..20_000u64 {
+                        gauge.increment(1.0);
+                    }
+                })
+            })
+            .collect();
+        let reader = {
+            let gauge = gauge.clone();
+            let done = done.clone();
+            spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    let (value, time) = gauge.snapshot();
+                    assert_eq!(value, time as f64);
+                }
+            })
+        };
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        reader.join().unwrap();
+    }
+
     #[test]
     fn test_counter() {
         let value = CounterValue::default();
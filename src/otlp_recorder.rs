@@ -1,44 +1,180 @@
 use core::time::Duration;
 use std::{
-    sync::{Arc, Mutex},
-    vec,
+    borrow::Cow,
+    collections::HashMap,
+    convert::Infallible,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
 };
 
-use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+use ::json::JsonValue;
+use metrics::{
+    set_global_recorder, Counter, Gauge, Histogram, Key, KeyName, Label, Level, Metadata, Recorder,
+    SetRecorderError, SharedString, Unit,
+};
+use tracing::{error, warn};
 
 use crate::{
+    clock::{Clock, SharedClock, TimePrecision},
+    exemplar::{TraceIdHook, Traced},
     json,
     metric::{
-        CounterValue, GaugeValue, HistogramValue, MetricData, MetricDescription, MetricType,
-        MetricValues,
+        description_buckets, CounterValue, DoubleCounterValue, ExponentialHistogramValue,
+        GaugeValue, HistogramValue, MetricData, MetricDescription, MetricType, MetricValues,
+        SummaryValue, BUCKETS_LABEL,
     },
-    time::current_time,
+    prometheus, protobuf,
+    snapshot::{self, MetricSnapshot},
+    transport::{send_metrics, Payload, TransportConfig, TransportError},
 };
 
+/// Descriptions added after registration are applied to the stored metric by
+/// `add_description`, the existing metric is returned as is
+///
+/// Further variants recorded through the same handle type, e.g. summaries for histograms,
+/// are returned as well.
 macro_rules! return_existing_metric {
-    ($self:ident, $key:ident, $mtype:ident) => {
-        if let Some(value) = $self
-            .metrics
-            .lock()
-            .expect("metrics lock")
-            .iter()
-            .find(|(k, _)| k.name() == $key.name())
-            .map(|(_, v)| match &v.metric_type {
-                MetricType::$mtype(v) => v.clone(),
-                v => panic!("Unexpected metric type {v} expected $mtype"),
-            })
-        {
-            return $mtype::from_arc(value);
+    ($self:ident, $key:ident, $mtype:ident $(, $variant:ident)*) => {
+        if let Some(metric) = $self.metrics.read().expect("metrics lock").get($key) {
+            return match &metric.metric_type {
+                MetricType::$mtype(v) => $mtype::from_arc(v.clone()),
+                $(MetricType::$variant(v) => $mtype::from_arc(v.clone()),)*
+                v => {
+                    error!(
+                        "Metric {} already registered as {v}, ignoring {}",
+                        $key,
+                        stringify!($mtype).to_lowercase()
+                    );
+                    $mtype::noop()
+                }
+            };
         }
     };
 }
 
+/// Recorder installed globally by this crate
+static INSTALLED: Mutex<Option<Arc<OtlpRecorder>>> = Mutex::new(None);
+
+type JsonTransform = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+const EXPORT_DURATION: &str = "otlp_export_duration_seconds";
+const EXPORTS: &str = "otlp_exports_total";
+const EXPORT_FAILURES: &str = "otlp_export_failures_total";
+const EXPORT_BYTES: &str = "otlp_export_bytes_total";
+const EXPORT_BODY_SIZE: &str = "otlp_export_body_size_bytes";
+
+static SELF_METADATA: Metadata<'static> =
+    Metadata::new(module_path!(), Level::INFO, Some(module_path!()));
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AggregationTemporality {
+    /// Counters and histograms report changes since the previous export
+    Delta = 1,
+    #[default]
+    Cumulative = 2,
+}
+
+/// How `Counter::absolute` treats a value lower than the current one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AbsoluteStrategy {
+    /// Keep the highest value, the counter never decreases
+    #[default]
+    Max,
+    /// Use the reported value, e.g. after the source of the counter restarted
+    Overwrite,
+}
+
+/// Which label is exported when a metric has the same label key more than once
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateLabels {
+    First,
+    #[default]
+    Last,
+}
+
+/// How json export handles NaN and infinite values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NonFiniteValues {
+    /// Export 0 and log a warning
+    #[default]
+    Zero,
+    /// Export NaN as 0 and infinity as the largest finite value with the same sign
+    Clamp,
+    /// Leave out the metric
+    Skip,
+}
+
+/// Resource attributes added by [`OtlpRecorder::detect_resources`], all enabled by default
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResourceDetectors {
+    /// `host.name`, left out when the hostname can not be read
+    pub host: bool,
+    /// `process.pid`
+    pub process: bool,
+    /// `process.runtime.name`
+    pub runtime: bool,
+    /// `os.type`
+    pub os: bool,
+}
+
+impl Default for ResourceDetectors {
+    fn default() -> Self {
+        Self {
+            host: true,
+            process: true,
+            runtime: true,
+            os: true,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct OtlpRecorder {
-    name: String,
-    version: String,
-    instance_id: String,
-    metrics: Mutex<MetricValues>,
+    pub(crate) name: String,
+    pub(crate) version: String,
+    pub(crate) instance_id: String,
+    pub(crate) resource_attributes: Vec<(String, String)>,
+    pub(crate) scope_name: String,
+    pub(crate) scope_version: String,
+    pub(crate) resource_schema_url: Option<String>,
+    pub(crate) scope_schema_url: Option<String>,
+    pub(crate) temporality: AggregationTemporality,
+    pub(crate) typed_attributes: bool,
+    pub(crate) non_finite_values: NonFiniteValues,
+    global_labels: Vec<(String, String)>,
+    duplicate_labels: DuplicateLabels,
+    namespace: String,
+    name_replacement: Option<char>,
+    lowercase_names: bool,
+    allowed_labels: Vec<String>,
+    denied_labels: Vec<String>,
+    double_counters: Vec<String>,
+    non_monotonic: Vec<String>,
+    exemplars: bool,
+    trace_id_attribute: Option<(String, TraceIdHook)>,
+    absolute_strategy: AbsoluteStrategy,
+    skip_unwritten: bool,
+    self_metrics: bool,
+    ttl: Option<Duration>,
+    process_start_time: bool,
+    /// Start time shared by series registered from now on, captured when the recorder is
+    /// created and moved to the last [`OtlpRecorder::clear`] or eviction so series registered
+    /// again start after their removal
+    process_start: AtomicU64,
+    max_series: Option<usize>,
+    dropped_series: AtomicU64,
+    clock: SharedClock,
+    json_transform: Option<JsonTransform>,
+    default_buckets: Vec<f64>,
+    metric_buckets: HashMap<String, Vec<f64>>,
+    metric_units: HashMap<String, String>,
+    summaries: Vec<(String, Vec<f64>)>,
+    exponential_histograms: Vec<String>,
+    /// Read locked to find registered metrics, values are updated through atomics
+    metrics: RwLock<MetricValues>,
     descriptions: Mutex<Vec<MetricDescription>>,
 }
 
@@ -48,32 +184,916 @@ impl OtlpRecorder {
             name: name.to_string(),
             version: version.to_string(),
             instance_id: instance_id.to_string(),
+            resource_attributes: Default::default(),
+            scope_name: env!("CARGO_PKG_NAME").to_string(),
+            scope_version: env!("CARGO_PKG_VERSION").to_string(),
+            resource_schema_url: None,
+            scope_schema_url: None,
+            temporality: Default::default(),
+            typed_attributes: false,
+            non_finite_values: Default::default(),
+            global_labels: Default::default(),
+            duplicate_labels: Default::default(),
+            namespace: Default::default(),
+            name_replacement: None,
+            lowercase_names: false,
+            allowed_labels: Default::default(),
+            denied_labels: Default::default(),
+            double_counters: Default::default(),
+            non_monotonic: Default::default(),
+            exemplars: false,
+            trace_id_attribute: None,
+            absolute_strategy: Default::default(),
+            skip_unwritten: false,
+            self_metrics: false,
+            ttl: None,
+            process_start_time: false,
+            process_start: Default::default(),
+            max_series: None,
+            dropped_series: Default::default(),
+            clock: Default::default(),
+            json_transform: None,
+            default_buckets: Default::default(),
+            metric_buckets: Default::default(),
+            metric_units: Default::default(),
+            summaries: Default::default(),
+            exponential_histograms: Default::default(),
             metrics: Default::default(),
             descriptions: Default::default(),
         }
+        .start_now()
     }
 
-    pub fn to_json(&self, period: Option<Duration>) -> String {
-        let metrics = self.metrics.lock().expect("metrics lock");
+    fn start_now(self) -> Self {
+        self.process_start
+            .store(self.clock.now(), Ordering::Relaxed);
+        self
+    }
 
-        let metrics_to_output: Vec<&(Key, MetricData)> = if let Some(p) = period {
-            metrics
-                .iter()
-                .filter(|(_, m)| match &m.metric_type {
-                    MetricType::Counter(v) => current_time() - v.time() <= p.as_nanos() as u64,
-                    MetricType::Gauge(v) => current_time() - v.time() <= p.as_nanos() as u64,
-                    MetricType::Histogram(v) => current_time() - v.time() <= p.as_nanos() as u64,
-                })
-                .collect()
+    /// Add resource attributes exported after service name, version and instance id
+    pub fn resource_attributes(mut self, attributes: Vec<(String, String)>) -> Self {
+        self.resource_attributes.extend(attributes);
+        self
+    }
+
+    /// Add host and process resource attributes as set by OpenTelemetry SDKs, attributes
+    /// already added with [`OtlpRecorder::resource_attributes`] are kept
+    pub fn detect_resources(self, detectors: ResourceDetectors) -> Self {
+        let os_type = match std::env::consts::OS {
+            "macos" => "darwin",
+            os => os,
+        };
+        let detected = [
+            (detectors.host, "host.name", crate::detect_hostname()),
+            (
+                detectors.process,
+                "process.pid",
+                Some(std::process::id().to_string()),
+            ),
+            (
+                detectors.runtime,
+                "process.runtime.name",
+                Some("rust".to_string()),
+            ),
+            (detectors.os, "os.type", Some(os_type.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, key, value)| {
+            let value = value.filter(|_| enabled)?;
+            (!self.resource_attributes.iter().any(|(k, _)| k == key))
+                .then(|| (key.to_string(), value))
+        })
+        .collect();
+        self.resource_attributes(detected)
+    }
+
+    /// Set instrumentation scope, defaults to this crate name and version
+    pub fn scope(mut self, name: impl ToString, version: impl ToString) -> Self {
+        self.scope_name = name.to_string();
+        self.scope_version = version.to_string();
+        self
+    }
+
+    /// Set the semantic conventions schema url of the resource attributes,
+    /// e.g. `https://opentelemetry.io/schemas/1.26.0`
+    pub fn resource_schema_url(mut self, url: impl ToString) -> Self {
+        self.resource_schema_url = Some(url.to_string());
+        self
+    }
+
+    /// Set the semantic conventions schema url of the instrumentation scope
+    pub fn scope_schema_url(mut self, url: impl ToString) -> Self {
+        self.scope_schema_url = Some(url.to_string());
+        self
+    }
+
+    /// Set aggregation temporality for counters and histograms
+    ///
+    /// With [`AggregationTemporality::Delta`] every export resets the exported values,
+    /// metrics without updates since the previous export are skipped.
+    pub fn aggregation_temporality(mut self, temporality: AggregationTemporality) -> Self {
+        self.temporality = temporality;
+        self
+    }
+
+    /// Export labels that parse as integers, finite floats or booleans with their typed
+    /// attribute value instead of `stringValue`
+    pub fn typed_attributes(mut self, typed: bool) -> Self {
+        self.typed_attributes = typed;
+        self
+    }
+
+    /// Set how NaN and infinite values are exported as json, defaults to exporting 0
+    pub fn non_finite_values(mut self, policy: NonFiniteValues) -> Self {
+        self.non_finite_values = policy;
+        self
+    }
+
+    /// Set which label is exported for a label key given more than once, defaults to the last
+    pub fn duplicate_labels(mut self, strategy: DuplicateLabels) -> Self {
+        self.duplicate_labels = strategy;
+        self
+    }
+
+    /// Add a label exported on every metric, labels of the metric take precedence
+    pub fn global_label(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.global_labels
+            .push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Export only attributes with allowed keys, all attributes are exported by default
+    ///
+    /// Series are not merged, series differing only by a dropped label are exported as
+    /// separate data points with the same attributes.
+    pub fn allow_label(mut self, key: impl ToString) -> Self {
+        self.allowed_labels.push(key.to_string());
+        self
+    }
+
+    /// Drop attributes with the key on export, e.g. high cardinality labels
+    ///
+    /// Series are not merged, see [`OtlpRecorder::allow_label`].
+    pub fn deny_label(mut self, key: impl ToString) -> Self {
+        self.denied_labels.push(key.to_string());
+        self
+    }
+
+    /// Prefix exported metric names with the namespace followed by `_`
+    pub fn namespace(mut self, namespace: impl ToString) -> Self {
+        self.namespace = namespace.to_string();
+        self
+    }
+
+    /// Replace characters outside of `[a-zA-Z_][a-zA-Z0-9_]*` in exported metric names and
+    /// label keys, e.g. `http.server.duration` becomes `http_server_duration` with `_`
+    ///
+    /// Label filters match the original label keys.
+    pub fn sanitize_names(mut self, replacement: char) -> Self {
+        self.name_replacement = Some(replacement);
+        self
+    }
+
+    /// Lowercase exported metric names and label keys
+    pub fn lowercase_names(mut self) -> Self {
+        self.lowercase_names = true;
+        self
+    }
+
+    /// Export gauges with the given name as monotonic sums with double values
+    ///
+    /// Use `gauge!(name).increment(value)` to record fractional counts, decrements are ignored.
+    pub fn double_counter(mut self, name: impl ToString) -> Self {
+        self.double_counters.push(name.to_string());
+        self
+    }
+
+    /// Export gauges with the given name as non monotonic sums with double values
+    ///
+    /// `gauge!(name).increment(value)` and `decrement(value)` add signed deltas, `set`
+    /// overwrites the value.
+    pub fn up_down_counter(mut self, name: impl ToString) -> Self {
+        self.double_counters.push(name.to_string());
+        self.non_monotonic.push(name.to_string());
+        self
+    }
+
+    /// Export sums with the given name as non monotonic, e.g. counters reset by their source
+    pub fn non_monotonic(mut self, name: impl ToString) -> Self {
+        self.non_monotonic.push(name.to_string());
+        self
+    }
+
+    /// Capture exemplars on counters and histograms recorded within a trace
+    ///
+    /// See [`crate::exemplar::set_trace_context`], histograms keep the latest exemplar per bucket.
+    pub fn exemplars(mut self, enabled: bool) -> Self {
+        self.exemplars = enabled;
+        self
+    }
+
+    /// Export the trace id returned by the hook as a data point attribute with the given key
+    ///
+    /// The hook runs on every update, e.g. reading the id of the current span, the latest
+    /// trace id of a series is exported. Updates without a trace id keep the previous one.
+    pub fn trace_id_attribute(
+        mut self,
+        key: impl ToString,
+        hook: impl Fn() -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.trace_id_attribute = Some((key.to_string(), Arc::new(hook)));
+        self
+    }
+
+    /// Set how counters handle `absolute` values lower than the current value
+    pub fn absolute_strategy(mut self, strategy: AbsoluteStrategy) -> Self {
+        self.absolute_strategy = strategy;
+        self
+    }
+
+    /// Skip metrics registered but never incremented, set or recorded on export
+    pub fn skip_unwritten(mut self, skip: bool) -> Self {
+        self.skip_unwritten = skip;
+        self
+    }
+
+    /// Record metrics about the exports of the interval sender through this recorder
+    ///
+    /// All metrics are labeled by `receiver`:
+    /// - `otlp_export_duration_seconds` gauge with the duration of the last successful export
+    /// - `otlp_exports_total` counter of export attempts
+    /// - `otlp_export_failures_total` counter of failed exports
+    /// - `otlp_export_bytes_total` counter of uncompressed bytes exported successfully
+    /// - `otlp_export_body_size_bytes` gauge with the request body size of the last successful
+    ///   export after compression, see [`TransportConfig::warn_body_size`]
+    ///
+    /// Values are recorded after an export, so they are sent with the next one.
+    pub fn self_metrics(mut self) -> Self {
+        self.self_metrics = true;
+        for (name, unit, description) in [
+            (
+                EXPORT_DURATION,
+                Unit::Seconds,
+                "Duration of the last successful export",
+            ),
+            (EXPORTS, Unit::Count, "Export attempts"),
+            (EXPORT_FAILURES, Unit::Count, "Failed exports"),
+            (EXPORT_BYTES, Unit::Bytes, "Uncompressed bytes exported"),
+            (
+                EXPORT_BODY_SIZE,
+                Unit::Bytes,
+                "Request body size of the last successful export",
+            ),
+        ] {
+            self.add_description(name.into(), Some(unit), description.into(), None);
+        }
+        self
+    }
+
+    /// Evict metrics without updates for longer than `ttl` on every export
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Limit the number of series, new series beyond the limit are not recorded
+    pub fn max_series(mut self, max_series: usize) -> Self {
+        self.max_series = Some(max_series);
+        self
+    }
+
+    /// Number of series registrations ignored because of [`OtlpRecorder::max_series`]
+    pub fn dropped_series(&self) -> u64 {
+        self.dropped_series.load(Ordering::Relaxed)
+    }
+
+    /// Set the clock used for metric timestamps, defaults to the system clock
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = SharedClock::new(clock).with_precision(self.clock.precision());
+        if self.metrics.get_mut().expect("metrics lock").is_empty() {
+            return self.start_now();
+        }
+        self
+    }
+
+    /// Share one start time across all series instead of the time each series is registered
+    ///
+    /// The start time is captured when the recorder is created, or when a clock is set before
+    /// any series is registered. Series registered after [`OtlpRecorder::clear`] or an eviction
+    /// start at the time of the last removal. Delta series start a new period at every export
+    /// as before.
+    pub fn process_start_time(mut self) -> Self {
+        self.process_start_time = true;
+        self
+    }
+
+    /// Truncate exported timestamps to the precision, defaults to nanoseconds
+    pub fn time_precision(mut self, precision: TimePrecision) -> Self {
+        self.clock = self.clock.with_precision(precision);
+        self
+    }
+
+    /// Transform serialized json before it is returned or sent, e.g. to wrap it in an envelope
+    ///
+    /// Applies to [`OtlpRecorder::to_json`], [`OtlpRecorder::to_writer`],
+    /// [`OtlpRecorder::flush_to`] and the interval sender. Split sends with
+    /// [`send_metrics_batched`](crate::transport::send_metrics_batched) and
+    /// [`send_metrics_udp`](crate::transport::send_metrics_udp) need the untransformed
+    /// document from [`OtlpRecorder::to_json_value`], their chunks are sent untransformed.
+    pub fn json_transform(
+        mut self,
+        transform: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.json_transform = Some(Box::new(transform));
+        self
+    }
+
+    /// Set bucket bounds for histograms registered without a `buckets` label
+    pub fn histogram_buckets(mut self, bounds: Vec<f64>) -> Self {
+        self.default_buckets = bounds;
+        self
+    }
+
+    /// Set bucket bounds for histograms with the given name, replacing bounds previously set for
+    /// it. A `buckets` label still takes precedence
+    pub fn metric_histogram_buckets(mut self, name: impl ToString, bounds: Vec<f64>) -> Self {
+        self.metric_buckets.insert(name.to_string(), bounds);
+        self
+    }
+
+    /// Export metrics with the given name with a free-form UCUM unit e.g. `By/s`, overriding the
+    /// unit provided by `describe_*` macros
+    pub fn metric_unit(mut self, name: impl ToString, unit: impl ToString) -> Self {
+        self.metric_units.insert(name.to_string(), unit.to_string());
+        self
+    }
+
+    /// Export histograms with the given name as summaries with estimated quantiles
+    ///
+    /// Quantiles are between 0 and 1, e.g. `vec![0.5, 0.9, 0.99]`, estimates are within 1%
+    /// of the recorded values.
+    pub fn summary(mut self, name: impl ToString, quantiles: Vec<f64>) -> Self {
+        self.summaries.push((name.to_string(), quantiles));
+        self
+    }
+
+    /// Export histograms with the given name as exponential histograms
+    ///
+    /// Buckets adapt to the recorded values, no bucket bounds need to be configured.
+    pub fn exponential_histogram(mut self, name: impl ToString) -> Self {
+        self.exponential_histograms.push(name.to_string());
+        self
+    }
+
+    /// Install recorder globally, fails when a global recorder is already installed
+    pub fn install(self) -> Result<Arc<Self>, SetRecorderError<Arc<Self>>> {
+        let mut installed = INSTALLED.lock().expect("installed lock");
+        self.install_locked(&mut installed)
+    }
+
+    /// Install recorder globally or return the recorder installed previously
+    ///
+    /// Fails when a global recorder of a different type is already installed.
+    pub fn install_or_existing(self) -> Result<Arc<Self>, SetRecorderError<Arc<Self>>> {
+        let mut installed = INSTALLED.lock().expect("installed lock");
+        if let Some(recorder) = installed.as_ref() {
+            return Ok(recorder.clone());
+        }
+        self.install_locked(&mut installed)
+    }
+
+    fn install_locked(
+        self,
+        installed: &mut Option<Arc<Self>>,
+    ) -> Result<Arc<Self>, SetRecorderError<Arc<Self>>> {
+        let recorder = Arc::new(self);
+        set_global_recorder(recorder.clone())?;
+        *installed = Some(recorder.clone());
+        Ok(recorder)
+    }
+
+    /// Serialize all metrics
+    ///
+    /// Metrics never written since registration are exported with their start time
+    /// as `timeUnixNano` unless [`OtlpRecorder::skip_unwritten`] is set.
+    ///
+    /// Series are selected under the metrics lock, their values are read afterwards while
+    /// serializing, so registration is not blocked and values updated during serialization
+    /// may or may not be included.
+    pub fn to_json(&self) -> String {
+        self.json_string(None)
+    }
+
+    /// Serialize metrics updated within the period, see [`OtlpRecorder::to_json`]
+    pub fn to_json_since(&self, period: Duration) -> String {
+        self.json_string(period.into())
+    }
+
+    fn json_string(&self, period: Option<Duration>) -> String {
+        let mut buffer = Vec::new();
+        self.writer(&mut buffer, period)
+            .expect("Writing to vec never fails");
+        String::from_utf8(buffer).expect("Json is valid utf8")
+    }
+
+    /// Metrics as a json document
+    ///
+    /// Useful to embed the metrics into a larger document or to pretty print them,
+    /// [`OtlpRecorder::json_transform`] is not applied.
+    ///
+    /// Like [`OtlpRecorder::to_json`] the document is an export, with delta temporality the
+    /// exported values are reset. Use [`OtlpRecorder::to_json_pretty`] to inspect them instead.
+    pub fn to_json_value(&self) -> JsonValue {
+        self.json_value(None)
+    }
+
+    /// Metrics updated within the period as a json document, see [`OtlpRecorder::to_json_value`]
+    pub fn to_json_value_since(&self, period: Duration) -> JsonValue {
+        self.json_value(period.into())
+    }
+
+    fn json_value(&self, period: Option<Duration>) -> JsonValue {
+        self.with_metrics(period, |metrics| json::root(self, metrics))
+    }
+
+    /// Metrics as indented json
+    ///
+    /// Meant for inspecting payloads, [`OtlpRecorder::json_transform`] is not applied and delta
+    /// values are not reset.
+    pub fn to_json_pretty(&self, indent: u16) -> String {
+        self.json_pretty(None, indent)
+    }
+
+    /// Metrics updated within the period as indented json, see [`OtlpRecorder::to_json_pretty`]
+    pub fn to_json_pretty_since(&self, period: Duration, indent: u16) -> String {
+        self.json_pretty(period.into(), indent)
+    }
+
+    fn json_pretty(&self, period: Option<Duration>, indent: u16) -> String {
+        let json = self.read_metrics(period, |metrics| json::root(self, metrics));
+        ::json::stringify_pretty(json, indent)
+    }
+
+    /// Write metrics as json without building an intermediate string
+    pub fn to_writer<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.writer(writer, None)
+    }
+
+    /// Write metrics updated within the period as json, see [`OtlpRecorder::to_writer`]
+    pub fn to_writer_since<W: Write>(&self, writer: &mut W, period: Duration) -> io::Result<()> {
+        self.writer(writer, period.into())
+    }
+
+    fn writer<W: Write>(&self, writer: &mut W, period: Option<Duration>) -> io::Result<()> {
+        self.with_metrics(period, |metrics| self.write_json(metrics, writer))
+    }
+
+    fn write_json<W: Write>(
+        &self,
+        metrics: &[(&Key, &MetricData)],
+        writer: &mut W,
+    ) -> io::Result<()> {
+        let Some(transform) = &self.json_transform else {
+            return json::write_metrics(self, metrics, writer);
+        };
+        let mut buffer = Vec::new();
+        json::write_metrics(self, metrics, &mut buffer)?;
+        let json = std::str::from_utf8(&buffer).expect("Json is valid utf8");
+        writer.write_all(transform(json).as_bytes())
+    }
+
+    /// Send metrics in the payload format of the config immediately, returns whether there
+    /// were any metrics to send
+    ///
+    /// Delta values are only reset once the metrics were sent, a failed send includes them
+    /// in the next one.
+    pub fn flush_to(&self, config: &TransportConfig) -> Result<bool, TransportError> {
+        self.flush(config, None)
+    }
+
+    /// Send metrics updated within the period, nothing is sent when none were updated,
+    /// see [`OtlpRecorder::flush_to`]
+    pub fn flush_to_since(
+        &self,
+        config: &TransportConfig,
+        period: Duration,
+    ) -> Result<bool, TransportError> {
+        self.flush(config, period.into())
+    }
+
+    fn flush(
+        &self,
+        config: &TransportConfig,
+        period: Option<Duration>,
+    ) -> Result<bool, TransportError> {
+        self.try_with_metrics(period, |metrics| {
+            if metrics.is_empty() {
+                return Ok(false);
+            }
+            send_metrics(config, &self.encode(metrics, config.payload)?)?;
+            Ok(true)
+        })
+    }
+
+    /// Serialize metrics in the payload format, json as [`OtlpRecorder::to_json`] and
+    /// protobuf as [`OtlpRecorder::to_protobuf`]
+    pub fn to_payload(&self, payload: Payload) -> Vec<u8> {
+        self.payload(payload, None)
+    }
+
+    /// Serialize metrics updated within the period in the payload format, see
+    /// [`OtlpRecorder::to_payload`]
+    pub fn to_payload_since(&self, payload: Payload, period: Duration) -> Vec<u8> {
+        self.payload(payload, period.into())
+    }
+
+    fn payload(&self, payload: Payload, period: Option<Duration>) -> Vec<u8> {
+        self.with_metrics(period, |metrics| self.encode(metrics, payload))
+            .expect("Writing to vec never fails")
+    }
+
+    /// Serialize once per payload format from the same metrics, delta values are reset by
+    /// [`OtlpRecorder::complete_export`] once a receiver accepted them
+    pub(crate) fn prepare_export(
+        &self,
+        payloads: &[Payload],
+        period: Option<Duration>,
+    ) -> PendingExport {
+        let selected = self.select(period, true);
+        let metrics = sorted(&selected);
+        let mut encoded: Vec<(Payload, Vec<u8>)> = Vec::new();
+        let bodies = payloads
+            .iter()
+            .map(|payload| {
+                if let Some((_, body)) = encoded.iter().find(|(p, _)| p == payload) {
+                    return body.clone();
+                }
+                let body = self
+                    .encode(&metrics, *payload)
+                    .expect("Writing to vec never fails");
+                encoded.push((*payload, body.clone()));
+                body
+            })
+            .collect();
+        PendingExport { bodies, selected }
+    }
+
+    /// Start a new delta period after the export was sent
+    pub(crate) fn complete_export(&self, export: PendingExport) {
+        self.reset_exported(&export.selected);
+    }
+
+    fn encode(&self, metrics: &[(&Key, &MetricData)], payload: Payload) -> io::Result<Vec<u8>> {
+        match payload {
+            Payload::Json => {
+                let mut buffer = Vec::new();
+                self.write_json(metrics, &mut buffer)?;
+                Ok(buffer)
+            }
+            Payload::Protobuf => Ok(protobuf::metrics_to_protobuf(self, metrics)),
+        }
+    }
+
+    /// Serialize metrics as an OTLP `ExportMetricsServiceRequest` protobuf message
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        self.payload(Payload::Protobuf, None)
+    }
+
+    /// Serialize metrics updated within the period as protobuf, see
+    /// [`OtlpRecorder::to_protobuf`]
+    pub fn to_protobuf_since(&self, period: Duration) -> Vec<u8> {
+        self.payload(Payload::Protobuf, period.into())
+    }
+
+    /// Copy all metrics
+    ///
+    /// Applies the same filtering and delta resets as [`OtlpRecorder::to_json`].
+    pub fn snapshot(&self) -> Vec<MetricSnapshot> {
+        self.snapshot_metrics(None)
+    }
+
+    /// Copy metrics updated within the period, see [`OtlpRecorder::snapshot`]
+    pub fn snapshot_since(&self, period: Duration) -> Vec<MetricSnapshot> {
+        self.snapshot_metrics(period.into())
+    }
+
+    fn snapshot_metrics(&self, period: Option<Duration>) -> Vec<MetricSnapshot> {
+        self.with_metrics(period, |metrics| {
+            snapshot::metrics_to_snapshot(self, metrics)
+        })
+    }
+
+    /// Add a fractional amount to a counter exported as a monotonic sum with `asDouble` values,
+    /// e.g. dollars or seconds of CPU time
+    ///
+    /// Bypasses the `counter!` macro which only takes `u64`, the series is registered on the
+    /// first call. Negative and NaN amounts are ignored unless the name is an
+    /// [`OtlpRecorder::up_down_counter`].
+    pub fn add_to_counter_f64(&self, name: &str, labels: &[(&str, &str)], delta: f64) {
+        let labels: Vec<Label> = labels
+            .iter()
+            .map(|(k, v)| Label::new(k.to_string(), v.to_string()))
+            .collect();
+        let key = Key::from_parts(name.to_string(), labels);
+        self.register_double_counter(&key).increment(delta);
+    }
+
+    /// Current value of the counter series, labels match in any order
+    ///
+    /// Reading does not reset values with delta temporality.
+    pub fn get_counter(&self, name: &str, labels: &[(&str, &str)]) -> Option<u64> {
+        match self.find_metric(name, labels)? {
+            MetricType::Counter(v) => Some(v.value()),
+            _ => None,
+        }
+    }
+
+    /// Current value of the gauge series, including gauges exported as double counters
+    pub fn get_gauge(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        match self.find_metric(name, labels)? {
+            MetricType::Gauge(v) => Some(v.value()),
+            MetricType::DoubleCounter(v) => Some(v.value()),
+            _ => None,
+        }
+    }
+
+    /// Number of values recorded into the histogram series, including histograms exported as
+    /// summaries or exponential histograms
+    pub fn get_histogram_count(&self, name: &str, labels: &[(&str, &str)]) -> Option<u64> {
+        match self.find_metric(name, labels)? {
+            MetricType::Histogram(v) => Some(v.count()),
+            MetricType::Summary(v) => Some(v.count()),
+            MetricType::ExponentialHistogram(v) => Some(v.snapshot().count),
+            _ => None,
+        }
+    }
+
+    /// Record an export when [`OtlpRecorder::self_metrics`] is set, the duration is `None`
+    /// when the export failed
+    pub(crate) fn record_export(
+        &self,
+        receiver: &str,
+        bytes: usize,
+        body_size: usize,
+        duration: Option<Duration>,
+    ) {
+        if !self.self_metrics {
+            return;
+        }
+        let key = |name| Key::from_parts(name, vec![Label::new("receiver", receiver.to_string())]);
+        self.register_counter(&key(EXPORTS), &SELF_METADATA)
+            .increment(1);
+        let Some(duration) = duration else {
+            self.register_counter(&key(EXPORT_FAILURES), &SELF_METADATA)
+                .increment(1);
+            return;
+        };
+        self.register_counter(&key(EXPORT_BYTES), &SELF_METADATA)
+            .increment(bytes as u64);
+        self.register_gauge(&key(EXPORT_BODY_SIZE), &SELF_METADATA)
+            .set(body_size as f64);
+        self.register_gauge(&key(EXPORT_DURATION), &SELF_METADATA)
+            .set(duration.as_secs_f64());
+    }
+
+    fn find_metric(&self, name: &str, labels: &[(&str, &str)]) -> Option<MetricType> {
+        let metrics = self.metrics.read().expect("metrics lock");
+        metrics
+            .iter()
+            .find(|(key, _)| {
+                key.name() == name
+                    && key.labels().len() == labels.len()
+                    && key.labels().all(|l| labels.contains(&(l.key(), l.value())))
+            })
+            .map(|(_, metric)| metric.metric_type.clone())
+    }
+
+    /// Serialize metrics in the Prometheus text exposition format
+    ///
+    /// Counters are exported with a `_total` suffix and histograms as cumulative
+    /// `_bucket`, `_sum` and `_count` samples.
+    ///
+    /// Scraping does not reset delta values and includes series unchanged since the last export.
+    pub fn to_prometheus(&self) -> String {
+        self.read_metrics(None, |metrics| {
+            prometheus::metrics_to_prometheus(self, metrics)
+        })
+    }
+
+    /// Serialize metrics in the OpenMetrics text format
+    ///
+    /// Like [`OtlpRecorder::to_prometheus`] with `# UNIT` metadata for metric names ending
+    /// with their unit, `_created` samples with the start time of counters, histograms and
+    /// summaries and a trailing `# EOF`.
+    pub fn to_openmetrics(&self) -> String {
+        self.read_metrics(None, |metrics| {
+            prometheus::metrics_to_openmetrics(self, metrics)
+        })
+    }
+
+    /// Remove all metrics and descriptions
+    ///
+    /// Handles obtained before clearing keep working but are no longer exported, the
+    /// `metrics` macros register a new series on their next call.
+    pub fn clear(&self) {
+        let mut metrics = self.metrics.write().expect("metrics lock");
+        if !metrics.is_empty() {
+            self.process_start
+                .store(self.clock.now(), Ordering::Relaxed);
+        }
+        metrics.clear();
+        self.descriptions.lock().expect("description lock").clear();
+    }
+
+    /// Zero counters and histograms while keeping registered series, gauges keep their value
+    ///
+    /// Values recorded concurrently with the reset may be lost.
+    pub fn reset_values(&self) {
+        let metrics = self.metrics.read().expect("metrics lock");
+        let start_time = self.clock.now();
+        for metric in metrics.values() {
+            metric.reset(start_time);
+        }
+    }
+
+    /// Remove metrics without updates for longer than `max_age`
+    ///
+    /// Handles of evicted metrics keep working but are no longer exported, the `metrics`
+    /// macros register a new series on their next call.
+    pub fn evict_stale(&self, max_age: Duration) {
+        let now = self.clock.now();
+        let mut metrics = self.metrics.write().expect("metrics lock");
+        let count = metrics.len();
+        metrics.retain(|_, m| is_fresh(m, now, max_age));
+        if metrics.len() < count {
+            self.process_start.store(now, Ordering::Relaxed);
+        }
+    }
+
+    /// Exported metric name including the namespace
+    pub(crate) fn metric_name<'a>(&self, key: &'a Key) -> Cow<'a, str> {
+        let name = if self.namespace.is_empty() {
+            Cow::Borrowed(key.name())
         } else {
-            metrics.iter().collect::<Vec<&(Key, MetricData)>>()
+            Cow::Owned(format!("{}_{}", self.namespace, key.name()))
         };
-        json::metrics_to_json(
-            &self.name,
-            &self.version,
-            &self.instance_id,
-            metrics_to_output.as_slice(),
-        )
+        self.export_name(name)
+    }
+
+    /// Apply [`OtlpRecorder::sanitize_names`] and [`OtlpRecorder::lowercase_names`]
+    fn export_name<'a>(&self, name: Cow<'a, str>) -> Cow<'a, str> {
+        let convert = |(i, c): (usize, char)| {
+            let c = if self.lowercase_names {
+                c.to_ascii_lowercase()
+            } else {
+                c
+            };
+            match self.name_replacement {
+                Some(replacement)
+                    if !(c.is_ascii_alphabetic() || c == '_' || i > 0 && c.is_ascii_digit()) =>
+                {
+                    replacement
+                }
+                _ => c,
+            }
+        };
+        if name.chars().enumerate().all(|(i, c)| convert((i, c)) == c) {
+            return name;
+        }
+        Cow::Owned(name.chars().enumerate().map(convert).collect())
+    }
+
+    /// Whether sums of the metric only increase
+    pub(crate) fn is_monotonic(&self, key: &Key) -> bool {
+        !self.non_monotonic.iter().any(|n| n == key.name())
+    }
+
+    /// Unit configured with [`OtlpRecorder::metric_unit`] or the described unit
+    pub(crate) fn unit<'a>(&'a self, key: &Key, data: &'a MetricData) -> &'a str {
+        self.metric_units
+            .get(key.name())
+            .map_or_else(|| data.unit(), String::as_str)
+    }
+
+    /// Metric labels followed by global labels not overridden by the metric, without
+    /// labels filtered by [`OtlpRecorder::allow_label`] and [`OtlpRecorder::deny_label`]
+    pub(crate) fn attributes<'a>(
+        &'a self,
+        key: &'a Key,
+        data: &MetricData,
+    ) -> impl Iterator<Item = (Cow<'a, str>, Cow<'a, str>)> {
+        self.all_attributes(key, data)
+            .filter(|(k, _)| self.label_exported(k))
+            .map(|(k, v)| (self.export_name(Cow::Borrowed(k)), v))
+    }
+
+    /// Number of attributes removed by label filters
+    pub(crate) fn dropped_attributes(&self, key: &Key, data: &MetricData) -> usize {
+        self.all_attributes(key, data)
+            .filter(|(k, _)| !self.label_exported(k))
+            .count()
+    }
+
+    fn all_attributes<'a>(
+        &'a self,
+        key: &'a Key,
+        data: &MetricData,
+    ) -> impl Iterator<Item = (&'a str, Cow<'a, str>)> {
+        let duplicate_labels = self.duplicate_labels;
+        data.labels(key)
+            .filter(move |label| {
+                let mut same_key = key.labels().filter(|l| l.key() == label.key());
+                let exported = match duplicate_labels {
+                    DuplicateLabels::First => same_key.next(),
+                    DuplicateLabels::Last => same_key.next_back(),
+                };
+                exported.is_some_and(|l| std::ptr::eq(l, *label))
+            })
+            .map(|l| (l.key(), Cow::Borrowed(l.value())))
+            .chain(
+                self.global_labels
+                    .iter()
+                    .filter(|(k, _)| !key.labels().any(|l| l.key() == k))
+                    .map(|(k, v)| (k.as_str(), Cow::Borrowed(v.as_str()))),
+            )
+            .chain(self.trace_id_attribute.as_ref().and_then(|(k, _)| {
+                let trace_id = data.trace_id.lock().expect("trace id lock").clone()?;
+                Some((k.as_str(), Cow::Owned(trace_id)))
+            }))
+    }
+
+    fn label_exported(&self, key: &str) -> bool {
+        (self.allowed_labels.is_empty() || self.allowed_labels.iter().any(|l| l == key))
+            && !self.denied_labels.iter().any(|l| l == key)
+    }
+
+    fn with_metrics<T>(
+        &self,
+        period: Option<Duration>,
+        output: impl FnOnce(&[(&Key, &MetricData)]) -> T,
+    ) -> T {
+        self.try_with_metrics(period, |metrics| Ok::<_, Infallible>(output(metrics)))
+            .unwrap_or_else(|e| match e {})
+    }
+
+    /// Read current values without exporting them, delta values are not reset
+    fn read_metrics<T>(
+        &self,
+        period: Option<Duration>,
+        output: impl FnOnce(&[(&Key, &MetricData)]) -> T,
+    ) -> T {
+        output(&sorted(&self.select(period, false)))
+    }
+
+    /// Delta values are reset only when the output succeeds
+    fn try_with_metrics<T, E>(
+        &self,
+        period: Option<Duration>,
+        output: impl FnOnce(&[(&Key, &MetricData)]) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let selected = self.select(period, true);
+        let result = output(&sorted(&selected))?;
+        self.reset_exported(&selected);
+        Ok(result)
+    }
+
+    /// Series updated within the period, copies share the values with the registered
+    /// metrics so serializing them does not hold the lock
+    ///
+    /// For an export under delta temporality unchanged series are skipped and values are
+    /// copied to subtract exactly what was exported with [`OtlpRecorder::reset_exported`].
+    fn select(&self, period: Option<Duration>, export: bool) -> Vec<(Key, MetricData)> {
+        if let Some(ttl) = self.ttl {
+            self.evict_stale(ttl);
+        }
+
+        let delta = export && self.temporality == AggregationTemporality::Delta;
+        self.metrics
+            .read()
+            .expect("metrics lock")
+            .iter()
+            .filter(|(_, m)| {
+                let time = m.time();
+                period.is_none_or(|p| updated_within(time, self.clock.now(), p))
+                    && (!delta || time >= m.start_time())
+                    && (!self.skip_unwritten || time != 0)
+            })
+            .map(|(k, m)| (k.clone(), if delta { m.frozen() } else { m.clone() }))
+            .collect()
+    }
+
+    /// Subtract exported delta values, cumulative values are kept
+    fn reset_exported(&self, selected: &[(Key, MetricData)]) {
+        if self.temporality != AggregationTemporality::Delta {
+            return;
+        }
+        let start_time = self.clock.now();
+        let metrics = self.metrics.read().expect("metrics lock");
+        for (key, exported) in selected {
+            if let Some(metric) = metrics.get(key) {
+                metric.reset_exported(start_time, exported);
+            }
+        }
     }
 
     fn update_description(&self, key: &str, metric: &mut MetricData) {
@@ -89,88 +1109,280 @@ impl OtlpRecorder {
         }
     }
 
-    fn add_description(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+    fn described_buckets(&self, key: &str) -> Option<Vec<f64>> {
         self.descriptions
             .lock()
-            .expect("metrics lock")
-            .push(MetricDescription {
+            .expect("description lock")
+            .iter()
+            .find(|d| d.key.as_str() == key)
+            .and_then(|d| d.buckets.clone())
+    }
+
+    fn add_description(
+        &self,
+        key: KeyName,
+        unit: Option<Unit>,
+        description: SharedString,
+        buckets: Option<Vec<f64>>,
+    ) {
+        // metrics are locked first so a concurrent add_metric can not miss the description
+        let mut metrics = self.metrics.write().expect("metrics lock");
+        for (_, metric) in metrics.iter_mut().filter(|(k, _)| k.name() == key.as_str()) {
+            metric.description = description.clone();
+            metric.unit = unit;
+        }
+
+        let mut descriptions = self.descriptions.lock().expect("description lock");
+        if let Some(d) = descriptions.iter_mut().find(|d| d.key == key) {
+            d.description = description;
+            d.unit = unit;
+            d.buckets = buckets;
+        } else {
+            descriptions.push(MetricDescription {
                 key,
                 description,
                 unit,
+                buckets,
             });
+        }
     }
 
-    fn add_metric(&self, key: Key, mut metric: MetricData) {
+    fn add_metric(&self, key: Key, mut metric: MetricData) -> Registration {
+        let mut metrics = self.metrics.write().expect("metrics lock");
+        if metrics.contains_key(&key) {
+            return Registration::Existing;
+        }
+        if self.max_series.is_some_and(|max| metrics.len() >= max) {
+            if self.dropped_series.fetch_add(1, Ordering::Relaxed) == 0 {
+                warn!("Series limit {} reached, ignoring {key}", metrics.len());
+            }
+            return Registration::Limited;
+        }
+        if self.process_start_time {
+            *metric.start_time.get_mut() = self.process_start.load(Ordering::Relaxed);
+        }
         self.update_description(key.name(), &mut metric);
+        metrics.insert(key, metric);
+        Registration::Added
+    }
 
-        self.metrics
-            .lock()
+    /// Handle capturing the trace id of every update, when a trace id attribute is set and
+    /// the series is registered
+    fn traced<T>(&self, key: &Key, handle: T) -> Option<Traced<T>> {
+        let (_, hook) = self.trace_id_attribute.as_ref()?;
+        let slot = self
+            .metrics
+            .read()
             .expect("metrics lock")
-            .push((key, metric));
+            .get(key)?
+            .trace_id
+            .clone();
+        Some(Traced::new(handle, hook.clone(), slot))
     }
-}
 
-impl Recorder for OtlpRecorder {
-    fn describe_counter(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
-        self.add_description(key, unit, description);
-    }
+    fn counter(&self, key: &Key) -> Counter {
+        return_existing_metric!(self, key, Counter);
 
-    fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
-        self.add_description(key, unit, description);
-    }
+        let value = Arc::new(
+            CounterValue::default()
+                .with_clock(self.clock.clone())
+                .with_exemplars(self.exemplars)
+                .with_absolute_strategy(self.absolute_strategy),
+        );
+        let metric = MetricData::basic(MetricType::Counter(value.clone()), self.clock.now());
 
-    fn describe_histogram(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
-        self.add_description(key, unit, description);
+        match self.add_metric(key.clone(), metric) {
+            Registration::Added => Counter::from_arc(value),
+            Registration::Existing => self.counter(key),
+            Registration::Limited => Counter::noop(),
+        }
     }
 
-    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
-        return_existing_metric!(self, key, Counter);
+    fn gauge(&self, key: &Key) -> Gauge {
+        return_existing_metric!(self, key, Gauge, DoubleCounter);
 
-        let value = Arc::new(CounterValue::default());
-        let metric = MetricData::basic(MetricType::Counter(value.clone()));
+        if self.double_counters.iter().any(|n| n == key.name()) {
+            return self.register_double_counter(key);
+        }
 
-        self.add_metric(key.clone(), metric);
+        let value = Arc::new(GaugeValue::default().with_clock(self.clock.clone()));
+        let metric = MetricData::basic(MetricType::Gauge(value.clone()), self.clock.now());
 
-        Counter::from_arc(value)
+        match self.add_metric(key.clone(), metric) {
+            Registration::Added => Gauge::from_arc(value),
+            Registration::Existing => self.gauge(key),
+            Registration::Limited => Gauge::noop(),
+        }
     }
 
-    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
-        return_existing_metric!(self, key, Gauge);
-
-        let value = Arc::new(GaugeValue::default());
-        let metric = MetricData::basic(MetricType::Gauge(value.clone()));
-
-        self.add_metric(key.clone(), metric);
+    fn register_double_counter(&self, key: &Key) -> Gauge {
+        if let Some(metric) = self.metrics.read().expect("metrics lock").get(key) {
+            return match &metric.metric_type {
+                MetricType::DoubleCounter(v) => Gauge::from_arc(v.clone()),
+                v => {
+                    error!("Metric {key} already registered as {v}, ignoring double counter");
+                    Gauge::noop()
+                }
+            };
+        }
 
-        Gauge::from_arc(value)
+        let value = Arc::new(
+            DoubleCounterValue::default()
+                .with_clock(self.clock.clone())
+                .with_up_down(!self.is_monotonic(key)),
+        );
+        let metric = MetricData::basic(MetricType::DoubleCounter(value.clone()), self.clock.now());
+        match self.add_metric(key.clone(), metric) {
+            Registration::Added => Gauge::from_arc(value),
+            Registration::Existing => self.register_double_counter(key),
+            Registration::Limited => Gauge::noop(),
+        }
     }
 
-    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
-        return_existing_metric!(self, key, Histogram);
+    fn histogram(&self, key: &Key) -> Histogram {
+        return_existing_metric!(self, key, Histogram, Summary, ExponentialHistogram);
 
-        let key = key.clone();
+        if self.exponential_histograms.iter().any(|n| n == key.name()) {
+            let value =
+                Arc::new(ExponentialHistogramValue::default().with_clock(self.clock.clone()));
+            let metric = MetricData::basic(
+                MetricType::ExponentialHistogram(value.clone()),
+                self.clock.now(),
+            );
+            return match self.add_metric(key.clone(), metric) {
+                Registration::Added => Histogram::from_arc(value),
+                Registration::Existing => self.histogram(key),
+                Registration::Limited => Histogram::noop(),
+            };
+        }
+
+        if let Some((_, quantiles)) = self
+            .summaries
+            .iter()
+            .rev()
+            .find(|(name, _)| name == key.name())
+        {
+            let value =
+                Arc::new(SummaryValue::new(quantiles.clone()).with_clock(self.clock.clone()));
+            let metric = MetricData::basic(MetricType::Summary(value.clone()), self.clock.now());
+            return match self.add_metric(key.clone(), metric) {
+                Registration::Added => Histogram::from_arc(value),
+                Registration::Existing => self.histogram(key),
+                Registration::Limited => Histogram::noop(),
+            };
+        }
 
         let bounds = if let Some(buckets) = key
             .labels()
-            .find_map(|l| (l.key() == "buckets").then_some(l.value()))
+            .find_map(|l| (l.key() == BUCKETS_LABEL).then_some(l.value()))
         {
             buckets
                 .split(',')
-                .map(|v| {
-                    v.trim()
-                        .parse()
-                        .unwrap_or_else(|_| panic!("Invalid value for bucket provided {v}"))
+                .map(|v| v.trim().parse().ok())
+                .collect::<Option<Vec<f64>>>()
+                .unwrap_or_else(|| {
+                    error!(
+                        "Invalid histogram buckets {buckets} for {key}, recording without buckets"
+                    );
+                    Vec::new()
                 })
-                .collect()
+        } else if let Some(bounds) = self.described_buckets(key.name()) {
+            bounds
+        } else if let Some(bounds) = self.metric_buckets.get(key.name()) {
+            bounds.clone()
         } else {
-            vec![]
+            self.default_buckets.clone()
         };
 
-        let value = Arc::new(HistogramValue::from_bounds(bounds));
-        let metric = MetricData::basic(MetricType::Histogram(value.clone()));
+        let value = Arc::new(
+            HistogramValue::from_bounds(bounds)
+                .with_clock(self.clock.clone())
+                .with_exemplars(self.exemplars),
+        );
+        let metric = MetricData::basic(MetricType::Histogram(value.clone()), self.clock.now());
+
+        match self.add_metric(key.clone(), metric) {
+            Registration::Added => Histogram::from_arc(value),
+            Registration::Existing => self.histogram(key),
+            Registration::Limited => Histogram::noop(),
+        }
+    }
+}
+
+/// Serialized export waiting for a receiver to accept it
+pub(crate) struct PendingExport {
+    /// One body per requested payload format
+    pub(crate) bodies: Vec<Vec<u8>>,
+    selected: Vec<(Key, MetricData)>,
+}
+
+/// Series in a stable order regardless of storage and the order labels were given in
+fn sorted(selected: &[(Key, MetricData)]) -> Vec<(&Key, &MetricData)> {
+    let mut metrics: Vec<(&Key, &MetricData)> = selected.iter().map(|(k, m)| (k, m)).collect();
+    metrics.sort_by_cached_key(|(k, _)| {
+        let mut labels: Vec<_> = k.labels().map(|l| (l.key(), l.value())).collect();
+        labels.sort_unstable();
+        (k.name(), labels)
+    });
+    metrics
+}
+
+/// Outcome of [`OtlpRecorder::add_metric`]
+enum Registration {
+    Added,
+    /// Registered by another thread after the lookup, the existing metric is kept
+    Existing,
+    /// The series limit prevents adding a new series
+    Limited,
+}
+
+/// Metrics registered but never updated are kept for max_age after registration
+fn is_fresh(metric: &MetricData, now: u64, max_age: Duration) -> bool {
+    now.saturating_sub(metric.time().max(metric.registered)) <= max_age.as_nanos() as u64
+}
+
+/// Metrics updated after `now`, e.g. by a concurrent update, are within any period
+pub(crate) fn updated_within(time: u64, now: u64, period: Duration) -> bool {
+    now.saturating_sub(time) <= period.as_nanos() as u64
+}
+
+impl Recorder for OtlpRecorder {
+    fn describe_counter(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.add_description(key, unit, description, None);
+    }
+
+    fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.add_description(key, unit, description, None);
+    }
 
-        self.add_metric(key, metric);
+    /// Bucket bounds can be given at the end of the description as `[buckets=10,30]`, they
+    /// apply to histograms registered after the description
+    fn describe_histogram(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        let (description, buckets) = description_buckets(description);
+        self.add_description(key, unit, description, buckets);
+    }
 
-        Histogram::from_arc(value)
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        let counter = self.counter(key);
+        match self.traced(key, counter.clone()) {
+            Some(traced) => Counter::from_arc(Arc::new(traced)),
+            None => counter,
+        }
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        let gauge = self.gauge(key);
+        match self.traced(key, gauge.clone()) {
+            Some(traced) => Gauge::from_arc(Arc::new(traced)),
+            None => gauge,
+        }
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        let histogram = self.histogram(key);
+        match self.traced(key, histogram.clone()) {
+            Some(traced) => Histogram::from_arc(Arc::new(traced)),
+            None => histogram,
+        }
     }
 }
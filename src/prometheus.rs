@@ -0,0 +1,252 @@
+use std::fmt::Write;
+
+use metrics::Key;
+
+use crate::{
+    metric::{MetricData, MetricType},
+    otlp_recorder::OtlpRecorder,
+};
+
+pub fn metrics_to_prometheus(recorder: &OtlpRecorder, values: &[(&Key, &MetricData)]) -> String {
+    metrics_to_text(recorder, values, false)
+}
+
+/// OpenMetrics adds `# UNIT` metadata, `_created` samples with the start time and `# EOF`
+pub fn metrics_to_openmetrics(recorder: &OtlpRecorder, values: &[(&Key, &MetricData)]) -> String {
+    let mut output = metrics_to_text(recorder, values, true);
+    output.push_str("# EOF\n");
+    output
+}
+
+fn metrics_to_text(
+    recorder: &OtlpRecorder,
+    values: &[(&Key, &MetricData)],
+    open_metrics: bool,
+) -> String {
+    let mut output = String::new();
+    let mut previous: Option<(String, &str)> = None;
+    for (key, data) in values {
+        let (name, metric_type) = match &data.metric_type {
+            MetricType::Counter(_) | MetricType::DoubleCounter(_)
+                if !recorder.is_monotonic(key) =>
+            {
+                (sanitize(&recorder.metric_name(key)), "gauge")
+            }
+            MetricType::Counter(_) | MetricType::DoubleCounter(_) => {
+                (counter_name(&recorder.metric_name(key)), "counter")
+            }
+            MetricType::Gauge(_) => (sanitize(&recorder.metric_name(key)), "gauge"),
+            MetricType::Histogram(_) => (sanitize(&recorder.metric_name(key)), "histogram"),
+            MetricType::Summary(_) => (sanitize(&recorder.metric_name(key)), "summary"),
+            MetricType::ExponentialHistogram(_) => {
+                (sanitize(&recorder.metric_name(key)), "histogram")
+            }
+        };
+        // OpenMetrics counter families are named without the `_total` suffix of their samples
+        let family = match name.strip_suffix("_total") {
+            Some(family) if open_metrics && metric_type == "counter" => family.to_string(),
+            _ => name.clone(),
+        };
+        let current = Some((name.clone(), metric_type));
+        if previous != current {
+            if !data.description.is_empty() {
+                let help = if open_metrics {
+                    escape_label_value(&data.description)
+                } else {
+                    escape_help(&data.description)
+                };
+                let _ = writeln!(output, "# HELP {family} {help}");
+            }
+            let _ = writeln!(output, "# TYPE {family} {metric_type}");
+            // the family name must end with the unit, otherwise the unit is omitted
+            if let Some(unit) = open_metrics
+                .then(|| openmetrics_unit(recorder.unit(key, data)))
+                .flatten()
+                .filter(|unit| family.ends_with(&format!("_{unit}")))
+            {
+                let _ = writeln!(output, "# UNIT {family} {unit}");
+            }
+            previous = current;
+        }
+
+        let labels = labels(recorder, key, data);
+        match &data.metric_type {
+            MetricType::Counter(v) => sample(&mut output, &name, &labels, v.value() as f64),
+            MetricType::Gauge(v) => sample(&mut output, &name, &labels, v.value()),
+            MetricType::DoubleCounter(v) => sample(&mut output, &name, &labels, v.value()),
+            MetricType::ExponentialHistogram(v) => {
+                // classic buckets with the upper bounds of the exponential buckets
+                let snapshot = v.snapshot();
+                let bound = |index: i32| 2f64.powf(f64::from(index) * 2f64.powi(-snapshot.scale));
+                let negative = snapshot
+                    .negative
+                    .counts
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(i, c)| (-bound(snapshot.negative.offset + i as i32), *c));
+                let zero = (snapshot.zero_count > 0).then_some((0.0, snapshot.zero_count));
+                let positive = snapshot
+                    .positive
+                    .counts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| (bound(snapshot.positive.offset + i as i32 + 1), *c));
+                let mut cumulative = 0;
+                for (bound, count) in negative.chain(zero).chain(positive) {
+                    cumulative += count;
+                    let mut bucket_labels = labels.clone();
+                    bucket_labels.push(("le".to_string(), number(bound)));
+                    sample(
+                        &mut output,
+                        &format!("{name}_bucket"),
+                        &bucket_labels,
+                        cumulative as f64,
+                    );
+                }
+                let mut bucket_labels = labels.clone();
+                bucket_labels.push(("le".to_string(), "+Inf".to_string()));
+                sample(
+                    &mut output,
+                    &format!("{name}_bucket"),
+                    &bucket_labels,
+                    snapshot.count as f64,
+                );
+                sample(&mut output, &format!("{name}_sum"), &labels, snapshot.sum);
+                sample(
+                    &mut output,
+                    &format!("{name}_count"),
+                    &labels,
+                    snapshot.count as f64,
+                );
+            }
+            MetricType::Summary(v) => {
+                for (quantile, value) in v.quantile_values() {
+                    let mut quantile_labels = labels.clone();
+                    quantile_labels.push(("quantile".to_string(), number(quantile)));
+                    sample(&mut output, &name, &quantile_labels, value);
+                }
+                sample(&mut output, &format!("{name}_sum"), &labels, v.sum());
+                sample(
+                    &mut output,
+                    &format!("{name}_count"),
+                    &labels,
+                    v.count() as f64,
+                );
+            }
+            MetricType::Histogram(v) => {
+                let cumulative_counts = v.cumulative_bucket_counts();
+                // the overflow bucket counts every value, keeping +Inf and count consistent
+                let count = cumulative_counts
+                    .last()
+                    .copied()
+                    .unwrap_or_else(|| v.count());
+                for (bound, cumulative) in v.explicit_bounds().iter().zip(cumulative_counts) {
+                    let mut bucket_labels = labels.clone();
+                    bucket_labels.push(("le".to_string(), number(*bound)));
+                    sample(
+                        &mut output,
+                        &format!("{name}_bucket"),
+                        &bucket_labels,
+                        cumulative as f64,
+                    );
+                }
+                let mut bucket_labels = labels.clone();
+                bucket_labels.push(("le".to_string(), "+Inf".to_string()));
+                sample(
+                    &mut output,
+                    &format!("{name}_bucket"),
+                    &bucket_labels,
+                    count as f64,
+                );
+                sample(&mut output, &format!("{name}_sum"), &labels, v.sum());
+                sample(&mut output, &format!("{name}_count"), &labels, count as f64);
+            }
+        }
+        if open_metrics && metric_type != "gauge" {
+            let created = data.start_time() as f64 / 1_000_000_000.0;
+            sample(&mut output, &format!("{family}_created"), &labels, created);
+        }
+    }
+    output
+}
+
+fn openmetrics_unit(unit: &str) -> Option<&str> {
+    match unit {
+        "" | "1" => None,
+        "s" => Some("seconds"),
+        "ms" => Some("milliseconds"),
+        "us" => Some("microseconds"),
+        "ns" => Some("nanoseconds"),
+        "B" | "By" => Some("bytes"),
+        unit => unit
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            .then_some(unit),
+    }
+}
+
+fn sample(output: &mut String, name: &str, labels: &[(String, String)], value: f64) {
+    output.push_str(name);
+    if !labels.is_empty() {
+        output.push('{');
+        for (i, (k, v)) in labels.iter().enumerate() {
+            if i > 0 {
+                output.push(',');
+            }
+            let _ = write!(output, "{k}=\"{}\"", escape_label_value(v));
+        }
+        output.push('}');
+    }
+    let _ = writeln!(output, " {}", number(value));
+}
+
+fn labels(recorder: &OtlpRecorder, key: &Key, data: &MetricData) -> Vec<(String, String)> {
+    recorder
+        .attributes(key, data)
+        .map(|(k, v)| (sanitize(&k).replace(':', "_"), v.to_string()))
+        .collect()
+}
+
+fn counter_name(name: &str) -> String {
+    let name = sanitize(name);
+    if name.ends_with("_total") {
+        name
+    } else {
+        format!("{name}_total")
+    }
+}
+
+/// Replace characters not allowed in prometheus metric and label names
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if c.is_ascii_alphabetic() || c == '_' || c == ':' || (i > 0 && c.is_ascii_digit()) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn number(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape_help(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn escape_label_value(value: &str) -> String {
+    escape_help(value).replace('"', "\\\"")
+}
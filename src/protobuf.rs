@@ -0,0 +1,684 @@
+use metrics::Key;
+
+use crate::{
+    exemplar::Exemplar,
+    metric::{
+        AttributeValue, CounterValue, DoubleCounterValue, ExponentialBuckets,
+        ExponentialHistogramValue, GaugeValue, HistogramValue, MetricData, MetricType,
+        SummaryValue,
+    },
+    otlp_recorder::OtlpRecorder,
+};
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LEN: u64 = 2;
+
+/// Encode metrics as an OTLP `ExportMetricsServiceRequest`
+pub fn metrics_to_protobuf(recorder: &OtlpRecorder, values: &[(&Key, &MetricData)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    message(&mut buf, 1, &resource_metrics(recorder, values));
+    buf
+}
+
+fn resource_metrics(recorder: &OtlpRecorder, values: &[(&Key, &MetricData)]) -> Vec<u8> {
+    let mut resource = Vec::new();
+    for (key, value) in [
+        ("service.name", recorder.name.as_str()),
+        ("service.version", &recorder.version),
+        ("service.instance.id", &recorder.instance_id),
+    ]
+    .into_iter()
+    .chain(
+        recorder
+            .resource_attributes
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str())),
+    ) {
+        message(&mut resource, 1, &attr(key, value));
+    }
+
+    let mut scope = Vec::new();
+    string(&mut scope, 1, &recorder.scope_name);
+    string(&mut scope, 2, &recorder.scope_version);
+
+    let mut scope_metrics = Vec::new();
+    message(&mut scope_metrics, 1, &scope);
+    for (k, v) in values.iter().copied() {
+        let metric = match &v.metric_type {
+            MetricType::Counter(m) => counter(recorder, k, v, m),
+            MetricType::Gauge(m) => gauge(recorder, k, v, m),
+            MetricType::Histogram(m) => histogram(recorder, k, v, m),
+            MetricType::DoubleCounter(m) => double_counter(recorder, k, v, m),
+            MetricType::Summary(m) => summary(recorder, k, v, m),
+            MetricType::ExponentialHistogram(m) => exponential_histogram(recorder, k, v, m),
+        };
+        message(&mut scope_metrics, 2, &metric);
+    }
+    if let Some(url) = &recorder.scope_schema_url {
+        string(&mut scope_metrics, 3, url);
+    }
+
+    let mut buf = Vec::new();
+    message(&mut buf, 1, &resource);
+    message(&mut buf, 2, &scope_metrics);
+    if let Some(url) = &recorder.resource_schema_url {
+        string(&mut buf, 3, url);
+    }
+    buf
+}
+
+fn metric_header(recorder: &OtlpRecorder, key: &Key, data: &MetricData) -> Vec<u8> {
+    let mut buf = Vec::new();
+    string(&mut buf, 1, &recorder.metric_name(key));
+    string(&mut buf, 2, &data.description);
+    string(&mut buf, 3, recorder.unit(key, data));
+    buf
+}
+
+fn attributes(
+    recorder: &OtlpRecorder,
+    buf: &mut Vec<u8>,
+    field: u64,
+    key: &Key,
+    data: &MetricData,
+) {
+    for (key, value) in recorder.attributes(key, data) {
+        let value = if recorder.typed_attributes {
+            AttributeValue::from(value.as_ref())
+        } else {
+            AttributeValue::String(&value)
+        };
+        message(buf, field, &typed_attr(&key, value));
+    }
+}
+
+fn exemplars(buf: &mut Vec<u8>, field: u64, exemplars: Vec<Exemplar>) {
+    for exemplar in exemplars {
+        let mut value = Vec::new();
+        fixed64(&mut value, 2, exemplar.time);
+        fixed64(&mut value, 3, exemplar.value.to_bits());
+        message(&mut value, 4, &exemplar.trace.span_id);
+        message(&mut value, 5, &exemplar.trace.trace_id);
+        message(buf, field, &value);
+    }
+}
+
+fn counter(recorder: &OtlpRecorder, key: &Key, data: &MetricData, value: &CounterValue) -> Vec<u8> {
+    let mut point = Vec::new();
+    fixed64(&mut point, 2, data.start_time());
+    fixed64(&mut point, 3, data.point_time(value.time()));
+    fixed64(&mut point, 6, value.value());
+    exemplars(&mut point, 5, value.exemplars());
+    attributes(recorder, &mut point, 7, key, data);
+
+    let mut sum = Vec::new();
+    message(&mut sum, 1, &point);
+    varint_field(&mut sum, 2, recorder.temporality as u64);
+    varint_field(&mut sum, 3, recorder.is_monotonic(key).into());
+
+    let mut buf = metric_header(recorder, key, data);
+    message(&mut buf, 7, &sum);
+    buf
+}
+
+fn double_counter(
+    recorder: &OtlpRecorder,
+    key: &Key,
+    data: &MetricData,
+    value: &DoubleCounterValue,
+) -> Vec<u8> {
+    let mut point = Vec::new();
+    fixed64(&mut point, 2, data.start_time());
+    fixed64(&mut point, 3, data.point_time(value.time()));
+    fixed64(&mut point, 4, value.value().to_bits());
+    attributes(recorder, &mut point, 7, key, data);
+
+    let mut sum = Vec::new();
+    message(&mut sum, 1, &point);
+    varint_field(&mut sum, 2, recorder.temporality as u64);
+    varint_field(&mut sum, 3, recorder.is_monotonic(key).into());
+
+    let mut buf = metric_header(recorder, key, data);
+    message(&mut buf, 7, &sum);
+    buf
+}
+
+fn gauge(recorder: &OtlpRecorder, key: &Key, data: &MetricData, value: &GaugeValue) -> Vec<u8> {
+    let (gauge_value, time) = value.snapshot();
+    let mut point = Vec::new();
+    fixed64(&mut point, 2, data.start_time());
+    fixed64(&mut point, 3, data.point_time(time));
+    fixed64(&mut point, 4, gauge_value.to_bits());
+    attributes(recorder, &mut point, 7, key, data);
+
+    let mut gauge = Vec::new();
+    message(&mut gauge, 1, &point);
+
+    let mut buf = metric_header(recorder, key, data);
+    message(&mut buf, 5, &gauge);
+    buf
+}
+
+fn histogram(
+    recorder: &OtlpRecorder,
+    key: &Key,
+    data: &MetricData,
+    value: &HistogramValue,
+) -> Vec<u8> {
+    let mut point = Vec::new();
+    fixed64(&mut point, 2, data.start_time());
+    fixed64(&mut point, 3, data.point_time(value.time()));
+    let (count, bucket_counts) = value.counts();
+    fixed64(&mut point, 4, count);
+    fixed64(&mut point, 5, value.sum().to_bits());
+    packed_fixed64(&mut point, 6, bucket_counts.into_iter());
+    packed_fixed64(
+        &mut point,
+        7,
+        value.explicit_bounds().iter().map(|b| b.to_bits()),
+    );
+    exemplars(&mut point, 8, value.exemplars());
+    attributes(recorder, &mut point, 9, key, data);
+    if let (Some(min), Some(max)) = (value.min(), value.max()) {
+        fixed64(&mut point, 11, min.to_bits());
+        fixed64(&mut point, 12, max.to_bits());
+    }
+
+    let mut histogram = Vec::new();
+    message(&mut histogram, 1, &point);
+    varint_field(&mut histogram, 2, recorder.temporality as u64);
+
+    let mut buf = metric_header(recorder, key, data);
+    message(&mut buf, 9, &histogram);
+    buf
+}
+
+fn exponential_histogram(
+    recorder: &OtlpRecorder,
+    key: &Key,
+    data: &MetricData,
+    value: &ExponentialHistogramValue,
+) -> Vec<u8> {
+    let snapshot = value.snapshot();
+    let mut point = Vec::new();
+    attributes(recorder, &mut point, 1, key, data);
+    fixed64(&mut point, 2, data.start_time());
+    fixed64(&mut point, 3, data.point_time(value.time()));
+    fixed64(&mut point, 4, snapshot.count);
+    fixed64(&mut point, 5, snapshot.sum.to_bits());
+    varint_field(&mut point, 6, zigzag(snapshot.scale));
+    fixed64(&mut point, 7, snapshot.zero_count);
+    message(&mut point, 8, &exponential_buckets(&snapshot.positive));
+    message(&mut point, 9, &exponential_buckets(&snapshot.negative));
+    if let (Some(min), Some(max)) = (snapshot.min, snapshot.max) {
+        fixed64(&mut point, 12, min.to_bits());
+        fixed64(&mut point, 13, max.to_bits());
+    }
+
+    let mut histogram = Vec::new();
+    message(&mut histogram, 1, &point);
+    varint_field(&mut histogram, 2, recorder.temporality as u64);
+
+    let mut buf = metric_header(recorder, key, data);
+    message(&mut buf, 10, &histogram);
+    buf
+}
+
+fn exponential_buckets(buckets: &ExponentialBuckets) -> Vec<u8> {
+    let mut buf = Vec::new();
+    varint_field(&mut buf, 1, zigzag(buckets.offset));
+    if !buckets.counts.is_empty() {
+        let mut counts = Vec::new();
+        for count in &buckets.counts {
+            varint(&mut counts, *count);
+        }
+        message(&mut buf, 2, &counts);
+    }
+    buf
+}
+
+fn summary(recorder: &OtlpRecorder, key: &Key, data: &MetricData, value: &SummaryValue) -> Vec<u8> {
+    let mut point = Vec::new();
+    fixed64(&mut point, 2, data.start_time());
+    fixed64(&mut point, 3, data.point_time(value.time()));
+    fixed64(&mut point, 4, value.count());
+    fixed64(&mut point, 5, value.sum().to_bits());
+    for (quantile, value) in value.quantile_values() {
+        let mut quantile_value = Vec::new();
+        fixed64(&mut quantile_value, 1, quantile.to_bits());
+        fixed64(&mut quantile_value, 2, value.to_bits());
+        message(&mut point, 6, &quantile_value);
+    }
+    attributes(recorder, &mut point, 7, key, data);
+
+    let mut summary = Vec::new();
+    message(&mut summary, 1, &point);
+
+    let mut buf = metric_header(recorder, key, data);
+    message(&mut buf, 11, &summary);
+    buf
+}
+
+fn attr(key: &str, value: &str) -> Vec<u8> {
+    typed_attr(key, AttributeValue::String(value))
+}
+
+fn typed_attr(key: &str, value: AttributeValue) -> Vec<u8> {
+    let mut any_value = Vec::new();
+    match value {
+        AttributeValue::String(v) => string(&mut any_value, 1, v),
+        AttributeValue::Bool(v) => varint_field(&mut any_value, 2, v as u64),
+        AttributeValue::Int(v) => varint_field(&mut any_value, 3, v as u64),
+        AttributeValue::Double(v) => fixed64(&mut any_value, 4, v.to_bits()),
+    }
+
+    let mut buf = Vec::new();
+    string(&mut buf, 1, key);
+    message(&mut buf, 2, &any_value);
+    buf
+}
+
+fn varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn tag(buf: &mut Vec<u8>, field: u64, wire_type: u64) {
+    varint(buf, (field << 3) | wire_type);
+}
+
+fn varint_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+    tag(buf, field, WIRE_VARINT);
+    varint(buf, value);
+}
+
+fn zigzag(value: i32) -> u64 {
+    ((value << 1) ^ (value >> 31)) as u32 as u64
+}
+
+fn fixed64(buf: &mut Vec<u8>, field: u64, value: u64) {
+    tag(buf, field, WIRE_FIXED64);
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn packed_fixed64(buf: &mut Vec<u8>, field: u64, values: impl ExactSizeIterator<Item = u64>) {
+    if values.len() == 0 {
+        return;
+    }
+    tag(buf, field, WIRE_LEN);
+    varint(buf, values.len() as u64 * 8);
+    for value in values {
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn string(buf: &mut Vec<u8>, field: u64, value: &str) {
+    message(buf, field, value.as_bytes());
+}
+
+fn message(buf: &mut Vec<u8>, field: u64, value: &[u8]) {
+    tag(buf, field, WIRE_LEN);
+    varint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use std::sync::Arc;
+
+    use metrics::{counter, gauge, histogram, set_default_local_recorder};
+    use prost::Message;
+
+    use crate::{clock::ManualClock, otlp_recorder::OtlpRecorder};
+
+    // Subset of opentelemetry/proto/collector/metrics/v1/metrics_service.proto
+    #[derive(Clone, PartialEq, Message)]
+    struct ExportMetricsServiceRequest {
+        #[prost(message, repeated, tag = "1")]
+        resource_metrics: Vec<ResourceMetrics>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct ResourceMetrics {
+        #[prost(message, optional, tag = "1")]
+        resource: Option<Resource>,
+        #[prost(message, repeated, tag = "2")]
+        scope_metrics: Vec<ScopeMetrics>,
+        #[prost(string, tag = "3")]
+        schema_url: String,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Resource {
+        #[prost(message, repeated, tag = "1")]
+        attributes: Vec<KeyValue>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct ScopeMetrics {
+        #[prost(message, optional, tag = "1")]
+        scope: Option<InstrumentationScope>,
+        #[prost(message, repeated, tag = "2")]
+        metrics: Vec<Metric>,
+        #[prost(string, tag = "3")]
+        schema_url: String,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct InstrumentationScope {
+        #[prost(string, tag = "1")]
+        name: String,
+        #[prost(string, tag = "2")]
+        version: String,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Metric {
+        #[prost(string, tag = "1")]
+        name: String,
+        #[prost(string, tag = "2")]
+        description: String,
+        #[prost(string, tag = "3")]
+        unit: String,
+        #[prost(oneof = "Data", tags = "5, 7, 9, 10")]
+        data: Option<Data>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    enum Data {
+        #[prost(message, tag = "5")]
+        Gauge(Gauge),
+        #[prost(message, tag = "7")]
+        Sum(Sum),
+        #[prost(message, tag = "9")]
+        Histogram(Histogram),
+        #[prost(message, tag = "10")]
+        ExponentialHistogram(ExponentialHistogram),
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Gauge {
+        #[prost(message, repeated, tag = "1")]
+        data_points: Vec<NumberDataPoint>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Sum {
+        #[prost(message, repeated, tag = "1")]
+        data_points: Vec<NumberDataPoint>,
+        #[prost(int32, tag = "2")]
+        aggregation_temporality: i32,
+        #[prost(bool, tag = "3")]
+        is_monotonic: bool,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Histogram {
+        #[prost(message, repeated, tag = "1")]
+        data_points: Vec<HistogramDataPoint>,
+        #[prost(int32, tag = "2")]
+        aggregation_temporality: i32,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct NumberDataPoint {
+        #[prost(message, repeated, tag = "7")]
+        attributes: Vec<KeyValue>,
+        #[prost(fixed64, tag = "2")]
+        start_time_unix_nano: u64,
+        #[prost(fixed64, tag = "3")]
+        time_unix_nano: u64,
+        #[prost(oneof = "Value", tags = "4, 6")]
+        value: Option<Value>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    enum Value {
+        #[prost(double, tag = "4")]
+        AsDouble(f64),
+        #[prost(sfixed64, tag = "6")]
+        AsInt(i64),
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct HistogramDataPoint {
+        #[prost(message, repeated, tag = "9")]
+        attributes: Vec<KeyValue>,
+        #[prost(fixed64, tag = "2")]
+        start_time_unix_nano: u64,
+        #[prost(fixed64, tag = "3")]
+        time_unix_nano: u64,
+        #[prost(fixed64, tag = "4")]
+        count: u64,
+        #[prost(double, optional, tag = "5")]
+        sum: Option<f64>,
+        #[prost(fixed64, repeated, tag = "6")]
+        bucket_counts: Vec<u64>,
+        #[prost(double, repeated, tag = "7")]
+        explicit_bounds: Vec<f64>,
+        #[prost(double, optional, tag = "11")]
+        min: Option<f64>,
+        #[prost(double, optional, tag = "12")]
+        max: Option<f64>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct ExponentialHistogram {
+        #[prost(message, repeated, tag = "1")]
+        data_points: Vec<ExponentialHistogramDataPoint>,
+        #[prost(int32, tag = "2")]
+        aggregation_temporality: i32,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct ExponentialHistogramDataPoint {
+        #[prost(message, repeated, tag = "1")]
+        attributes: Vec<KeyValue>,
+        #[prost(fixed64, tag = "2")]
+        start_time_unix_nano: u64,
+        #[prost(fixed64, tag = "3")]
+        time_unix_nano: u64,
+        #[prost(fixed64, tag = "4")]
+        count: u64,
+        #[prost(double, optional, tag = "5")]
+        sum: Option<f64>,
+        #[prost(sint32, tag = "6")]
+        scale: i32,
+        #[prost(fixed64, tag = "7")]
+        zero_count: u64,
+        #[prost(message, optional, tag = "8")]
+        positive: Option<Buckets>,
+        #[prost(message, optional, tag = "9")]
+        negative: Option<Buckets>,
+        #[prost(double, optional, tag = "12")]
+        min: Option<f64>,
+        #[prost(double, optional, tag = "13")]
+        max: Option<f64>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Buckets {
+        #[prost(sint32, tag = "1")]
+        offset: i32,
+        #[prost(uint64, repeated, tag = "2")]
+        bucket_counts: Vec<u64>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct KeyValue {
+        #[prost(string, tag = "1")]
+        key: String,
+        #[prost(message, optional, tag = "2")]
+        value: Option<AnyValue>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct AnyValue {
+        #[prost(string, tag = "1")]
+        string_value: String,
+    }
+
+    fn kv(key: &str, value: &str) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value: Some(AnyValue {
+                string_value: value.to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_protobuf_round_trip() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_protobuf_round_trip").clock(
+            Arc::new(ManualClock::new(Duration::from_millis(1739394449205))),
+        );
+        let _guard = set_default_local_recorder(&recorder);
+
+        counter!("test_counter", "label1" => "label_value1").increment(2);
+        gauge!("test_gauge").set(-1.5);
+        histogram!("test_histogram", "buckets" => "10,30").record(20);
+
+        let request = ExportMetricsServiceRequest::decode(recorder.to_protobuf().as_slice())
+            .expect("valid protobuf");
+
+        assert_eq!(
+            request,
+            ExportMetricsServiceRequest {
+                resource_metrics: vec![ResourceMetrics {
+                    resource: Some(Resource {
+                        attributes: vec![
+                            kv("service.name", "otlp-metrics"),
+                            kv("service.version", "1"),
+                            kv("service.instance.id", "test_protobuf_round_trip"),
+                        ],
+                    }),
+                    scope_metrics: vec![ScopeMetrics {
+                        scope: Some(InstrumentationScope {
+                            name: "otlp-metrics-exporter".to_string(),
+                            version: env!("CARGO_PKG_VERSION").to_string(),
+                        }),
+                        metrics: vec![
+                            Metric {
+                                name: "test_counter".to_string(),
+                                description: String::new(),
+                                unit: "1".to_string(),
+                                data: Some(Data::Sum(Sum {
+                                    data_points: vec![NumberDataPoint {
+                                        attributes: vec![kv("label1", "label_value1")],
+                                        start_time_unix_nano: 1739394449205000000,
+                                        time_unix_nano: 1739394449205000000,
+                                        value: Some(Value::AsInt(2)),
+                                    }],
+                                    aggregation_temporality: 2,
+                                    is_monotonic: true,
+                                })),
+                            },
+                            Metric {
+                                name: "test_gauge".to_string(),
+                                description: String::new(),
+                                unit: "1".to_string(),
+                                data: Some(Data::Gauge(Gauge {
+                                    data_points: vec![NumberDataPoint {
+                                        attributes: vec![],
+                                        start_time_unix_nano: 1739394449205000000,
+                                        time_unix_nano: 1739394449205000000,
+                                        value: Some(Value::AsDouble(-1.5)),
+                                    }],
+                                })),
+                            },
+                            Metric {
+                                name: "test_histogram".to_string(),
+                                description: String::new(),
+                                unit: "1".to_string(),
+                                data: Some(Data::Histogram(Histogram {
+                                    data_points: vec![HistogramDataPoint {
+                                        attributes: vec![],
+                                        start_time_unix_nano: 1739394449205000000,
+                                        time_unix_nano: 1739394449205000000,
+                                        count: 1,
+                                        sum: Some(20.0),
+                                        bucket_counts: vec![0, 1, 0],
+                                        explicit_bounds: vec![10.0, 30.0],
+                                        min: Some(20.0),
+                                        max: Some(20.0),
+                                    }],
+                                    aggregation_temporality: 2,
+                                })),
+                            },
+                        ],
+                        schema_url: String::new(),
+                    }],
+                    schema_url: String::new(),
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn test_protobuf_exponential_histogram() {
+        let recorder =
+            OtlpRecorder::new("otlp-metrics", "1", "test_protobuf_exponential_histogram")
+                .clock(Arc::new(ManualClock::new(Duration::from_millis(
+                    1739394449205,
+                ))))
+                .exponential_histogram("test_exponential");
+        let _guard = set_default_local_recorder(&recorder);
+
+        histogram!("test_exponential").record(0);
+        histogram!("test_exponential").record(1);
+        histogram!("test_exponential").record(4);
+        histogram!("test_exponential").record(-2);
+
+        let request = ExportMetricsServiceRequest::decode(recorder.to_protobuf().as_slice())
+            .expect("valid protobuf");
+        let metric = &request.resource_metrics[0].scope_metrics[0].metrics[0];
+        let Some(Data::ExponentialHistogram(histogram)) = &metric.data else {
+            panic!("expected exponential histogram {metric:?}");
+        };
+        let point = &histogram.data_points[0];
+        assert_eq!(point.count, 4);
+        assert_eq!(point.sum, Some(3.0));
+        assert_eq!(point.zero_count, 1);
+        assert_eq!(point.min, Some(-2.0));
+        assert_eq!(point.max, Some(4.0));
+        assert_eq!(histogram.aggregation_temporality, 2);
+
+        let crate::snapshot::SnapshotValue::ExponentialHistogram(expected) =
+            &recorder.snapshot()[0].value
+        else {
+            panic!("expected exponential histogram snapshot");
+        };
+        assert_eq!(point.scale, expected.scale);
+        let positive = point.positive.as_ref().expect("positive buckets");
+        assert_eq!(positive.offset, expected.positive.offset);
+        assert_eq!(positive.bucket_counts, expected.positive.counts);
+        let negative = point.negative.as_ref().expect("negative buckets");
+        assert_eq!(negative.offset, expected.negative.offset);
+        assert_eq!(negative.bucket_counts, expected.negative.counts);
+    }
+
+    #[test]
+    fn test_protobuf_schema_urls() {
+        let recorder = OtlpRecorder::new("otlp-metrics", "1", "test_protobuf_schema_urls")
+            .resource_schema_url("https://opentelemetry.io/schemas/1.26.0")
+            .scope_schema_url("https://opentelemetry.io/schemas/1.24.0");
+
+        let request = ExportMetricsServiceRequest::decode(recorder.to_protobuf().as_slice())
+            .expect("valid protobuf");
+
+        let resource_metrics = &request.resource_metrics[0];
+        assert_eq!(
+            resource_metrics.schema_url,
+            "https://opentelemetry.io/schemas/1.26.0"
+        );
+        assert_eq!(
+            resource_metrics.scope_metrics[0].schema_url,
+            "https://opentelemetry.io/schemas/1.24.0"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use metrics::Key;
+
+pub use crate::metric::{ExponentialBuckets, ExponentialSnapshot};
+use crate::{
+    metric::{MetricData, MetricType},
+    otlp_recorder::OtlpRecorder,
+};
+
+/// Point in time copy of a metric series
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricSnapshot {
+    /// Name including the recorder namespace
+    pub name: String,
+    pub unit: String,
+    pub description: String,
+    /// Labels of the metric followed by global labels it does not override
+    pub labels: Vec<(String, String)>,
+    pub start_time: u64,
+    pub time: u64,
+    pub value: SnapshotValue,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SnapshotValue {
+    Counter(u64),
+    DoubleCounter(f64),
+    Gauge(f64),
+    Histogram {
+        count: u64,
+        sum: f64,
+        min: Option<f64>,
+        max: Option<f64>,
+        /// One more count than bounds, the last bucket counts values above the last bound
+        bucket_counts: Vec<u64>,
+        explicit_bounds: Vec<f64>,
+    },
+    ExponentialHistogram(ExponentialSnapshot),
+    Summary {
+        count: u64,
+        sum: f64,
+        /// Configured quantiles with their estimated values
+        quantiles: Vec<(f64, f64)>,
+    },
+}
+
+pub(crate) fn metrics_to_snapshot(
+    recorder: &OtlpRecorder,
+    values: &[(&Key, &MetricData)],
+) -> Vec<MetricSnapshot> {
+    values
+        .iter()
+        .map(|(key, data)| {
+            let (value, time) = match &data.metric_type {
+                MetricType::Counter(v) => (SnapshotValue::Counter(v.value()), v.time()),
+                MetricType::DoubleCounter(v) => (SnapshotValue::DoubleCounter(v.value()), v.time()),
+                MetricType::Gauge(v) => {
+                    let (value, time) = v.snapshot();
+                    (SnapshotValue::Gauge(value), time)
+                }
+                MetricType::Histogram(v) => {
+                    let (count, bucket_counts) = v.counts();
+                    (
+                        SnapshotValue::Histogram {
+                            count,
+                            sum: v.sum(),
+                            min: v.min(),
+                            max: v.max(),
+                            bucket_counts,
+                            explicit_bounds: v.explicit_bounds().to_vec(),
+                        },
+                        v.time(),
+                    )
+                }
+                MetricType::ExponentialHistogram(v) => {
+                    (SnapshotValue::ExponentialHistogram(v.snapshot()), v.time())
+                }
+                MetricType::Summary(v) => (
+                    SnapshotValue::Summary {
+                        count: v.count(),
+                        sum: v.sum(),
+                        quantiles: v.quantile_values(),
+                    },
+                    v.time(),
+                ),
+            };
+            MetricSnapshot {
+                name: recorder.metric_name(key).into_owned(),
+                unit: recorder.unit(key, data).to_string(),
+                description: data.description.to_string(),
+                labels: recorder
+                    .attributes(key, data)
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                start_time: data.start_time(),
+                time: data.point_time(time),
+                value,
+            }
+        })
+        .collect()
+}